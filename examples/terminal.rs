@@ -68,6 +68,7 @@ fn main() -> Result<(), String> {
             println!("Waiting for CPU to choose worst move...");
             get_cpu_move(&b, false)
         } else if s == "rate" {
+            print!("{}", b.eval_trace(b.get_turn_color()));
             continue;
         } else if s == "pass" {
             b = b.change_turn();
@@ -2,9 +2,16 @@ extern crate chess_engine;
 use chess_engine::*;
 use std::{
     convert::TryFrom,
+    env,
     io::{stdin, stdout, Write},
 };
 
+mod perft;
+mod search;
+mod tune;
+mod uci;
+mod xboard;
+
 fn input(prompt: impl std::fmt::Display) -> String {
     let mut s = String::new();
     print!("{}", prompt);
@@ -52,6 +59,20 @@ fn get_cpu_move(b: &Board, best: bool) -> Move {
 }
 
 fn main() -> Result<(), String> {
+    match env::args().nth(1).as_deref() {
+        Some("uci") => return uci::run(),
+        Some("xboard") => return xboard::run(),
+        Some("perft") => return perft::run(&env::args().skip(2).collect::<Vec<_>>()),
+        Some("tune") => return tune::run(&env::args().skip(2).collect::<Vec<_>>()),
+        Some(mode) => {
+            return Err(format!(
+                "unknown mode `{}`, expected uci, xboard, perft or tune",
+                mode
+            ))
+        }
+        None => {}
+    }
+
     let mut b = Board::default();
 
     println!("{}", b);
@@ -68,6 +89,7 @@ fn main() -> Result<(), String> {
             println!("Waiting for CPU to choose worst move...");
             get_cpu_move(&b, false)
         } else if s == "rate" {
+            print!("{}", b.eval_trace(b.get_turn_color()));
             continue;
         } else if s == "pass" {
             b = b.change_turn();
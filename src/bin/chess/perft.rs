@@ -0,0 +1,31 @@
+// move generation check, printing the nodes under each first move in the
+// same format as other engines, so the counts can be compared line by line
+use crate::uci::format_uci_move;
+use chess_engine::*;
+use std::time::Instant;
+
+const USAGE: &str = "usage: chess perft <depth> [fen]";
+
+pub fn run(args: &[String]) -> Result<(), String> {
+    let depth = args
+        .first()
+        .ok_or(USAGE)?
+        .parse::<u8>()
+        .map_err(|_| String::from(USAGE))?;
+    let board = match args.get(1..) {
+        Some(fen) if !fen.is_empty() => parse_fen(&fen.join(" "))?,
+        _ => Board::default(),
+    };
+
+    let start = Instant::now();
+    let mut nodes = if depth == 0 { 1 } else { 0 };
+    for (m, count) in board.perft_divide(depth) {
+        println!("{}: {}", format_uci_move(&board, m), count);
+        nodes += count;
+    }
+
+    println!();
+    println!("Nodes searched: {}", nodes);
+    println!("Time: {} ms", start.elapsed().as_millis());
+    Ok(())
+}
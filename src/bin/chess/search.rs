@@ -0,0 +1,181 @@
+// searches on a background thread, shared by the protocol front-ends
+use chess_engine::*;
+use std::{
+    mem,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard, PoisonError,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+// deepest iteration started when no depth is set
+pub const MAX_DEPTH: u8 = 32;
+
+// transposition table size in megabytes, unless set by the GUI
+pub const DEFAULT_HASH_MB: usize = 16;
+
+// how often a finished infinite search checks for `stop`
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+// the transposition table kept by a front-end between searches. each
+// search borrows it while it runs, so the next one starts with what it
+// learnt about the positions.
+pub struct Hash {
+    table: Arc<Mutex<TranspositionTable<'static>>>,
+    megabytes: usize,
+}
+
+impl Hash {
+    pub fn new(megabytes: usize) -> Self {
+        Hash {
+            table: Arc::new(Mutex::new(TranspositionTable::new(megabytes << 20))),
+            megabytes,
+        }
+    }
+
+    #[cfg(test)]
+    pub fn get_megabytes(&self) -> usize {
+        self.megabytes
+    }
+
+    // replace the table with an empty one of `megabytes`. this waits for
+    // the table, so any search must be stopped first.
+    pub fn resize(&mut self, megabytes: usize) {
+        if megabytes != self.megabytes {
+            *self.lock() = TranspositionTable::new(megabytes << 20);
+            self.megabytes = megabytes;
+        }
+    }
+
+    // forget every position, for a new game. any search must be stopped
+    // first.
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, TranspositionTable<'static>> {
+        // a search that panicked leaves the table usable
+        self.table.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+// when a search ends by itself
+pub struct Limits {
+    pub depth: u8,
+    pub nodes: Option<u64>,
+    pub deadline: Option<Instant>,
+    // keep the result until stopped, even once every iteration is done
+    pub infinite: bool,
+}
+
+// a search running in the background
+pub struct Search {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<Move>,
+}
+
+impl Search {
+    // search `board` for the best `multipv` lines until `limits` or `stop`,
+    // calling `on_iteration` after every completed depth and `on_done` with
+    // the move to play. the search uses the table in `hash` until it ends.
+    pub fn start(
+        board: Board,
+        history: Vec<Board>,
+        hash: &Hash,
+        multipv: usize,
+        limits: Limits,
+        on_iteration: impl Fn(&[SearchResult]) + Send + 'static,
+        on_done: impl FnOnce(Move) + Send + 'static,
+    ) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let worker_stop = stop.clone();
+        let table = hash.table.clone();
+        let handle = thread::spawn(move || {
+            let start = Instant::now();
+            let clock = || start.elapsed();
+            let search_limits = SearchLimits {
+                depth: Some(limits.depth),
+                nodes: limits.nodes,
+                movetime: None,
+                deadline: limits
+                    .deadline
+                    .map(|deadline| deadline.saturating_duration_since(start)),
+            };
+
+            let mut table = table.lock().unwrap_or_else(PoisonError::into_inner);
+            let borrowed = mem::replace(&mut *table, TranspositionTable::from_buffer(&mut []));
+            let mut searcher = Searcher::new(borrowed)
+                .set_history(&history)
+                .set_clock(&clock)
+                .set_stop(&worker_stop);
+            let best = searcher
+                .search_multipv(&board, search_limits, multipv, on_iteration)
+                .remove(0)
+                .best_move;
+            *table = searcher.into_table().into_owned();
+            drop(table);
+
+            while limits.infinite && !worker_stop.load(Ordering::Relaxed) {
+                thread::sleep(POLL_INTERVAL);
+            }
+
+            on_done(best);
+            best
+        });
+
+        Search { stop, handle }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    // wait for the search to end by itself
+    pub fn join(self) -> Move {
+        self.handle.join().unwrap_or(Move::Resign)
+    }
+
+    // end the search early, returning the move it found
+    pub fn stop(self) -> Move {
+        self.stop.store(true, Ordering::Relaxed);
+        self.join()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash() {
+        let mut hash = Hash::new(1);
+        let capacity = hash.lock().capacity();
+        let limits = || Limits {
+            depth: 3,
+            nodes: None,
+            deadline: None,
+            infinite: false,
+        };
+        let search = Search::start(Board::default(), vec![], &hash, 1, limits(), |_| {}, |_| {});
+        let best = search.join();
+
+        // the table is given back with the positions the search stored
+        let stored = hash
+            .lock()
+            .probe(&Board::default())
+            .map(|entry| entry.get_best_move());
+        assert_eq!(stored, Some(best));
+        assert_eq!(hash.lock().capacity(), capacity);
+        let search = Search::start(Board::default(), vec![], &hash, 1, limits(), |_| {}, |_| {});
+        assert_eq!(search.join(), best);
+
+        hash.clear();
+        assert!(hash.lock().probe(&Board::default()).is_none());
+        hash.resize(2);
+        assert_eq!(hash.get_megabytes(), 2);
+        assert_eq!(hash.lock().capacity(), 2 * capacity);
+    }
+}
@@ -0,0 +1,295 @@
+// texel tuning of the evaluation weights. every position is labelled with
+// the result of the game it came from, and the weights are nudged one at a
+// time for as long as the evaluation, squashed into an expected score,
+// predicts those results better.
+//
+// each line of the positions file is a FEN followed by the result from
+// white's side, as `1-0`, `0-1` or `1/2-1/2`, or as 1, 0.5 or 0. the result
+// may be in brackets or quotes, and EPD lines like `<fen> c9 "1-0";` work
+// too. only quiet positions are used, where the player to move isn't in
+// check and can't capture anything, so the static evaluation isn't thrown
+// off by a piece that's about to be taken.
+//
+// the weights start from the parameters file if it exists, and are written
+// back to it after every pass that improves them, so a run can be stopped
+// and picked up again later.
+use chess_engine::*;
+use std::{fs, io};
+
+const USAGE: &str = "usage: chess tune <positions> <params> [parameter...]";
+
+// the first change tried for each weight, in centipawns. it's halved when
+// a pass over the weights doesn't improve any of them, down to 1.
+const FIRST_STEP: i32 = 8;
+
+// the largest scale fitted for the evaluation. without it, a few positions
+// that are all predicted right would push it up forever.
+const MAX_SCALE: f64 = 10.0;
+
+pub fn run(args: &[String]) -> Result<(), String> {
+    let positions_path = args.first().ok_or(USAGE)?;
+    let params_path = args.get(1).ok_or(USAGE)?;
+    let names = &args[2..];
+
+    let text = fs::read_to_string(positions_path)
+        .map_err(|e| format!("could not read {}: {}", positions_path, e))?;
+    let positions = parse_positions(&text)?;
+    if positions.is_empty() {
+        return Err(format!("no quiet positions in {}", positions_path));
+    }
+
+    let mut params = read_params(params_path)?;
+    let weights = select_weights(&mut params.clone(), names)?;
+
+    let scale = fit_scale(&params, &positions);
+    let mut error = mean_error(&params, &positions, scale);
+    eprintln!(
+        "{} quiet positions, {} weights, scale {:.3}, error {:.6}",
+        positions.len(),
+        weights.len(),
+        scale,
+        error
+    );
+
+    let mut step = FIRST_STEP;
+    while step > 0 {
+        let tuned = tune_pass(&mut params, &positions, scale, &weights, step, error);
+        if tuned < error {
+            error = tuned;
+            eprintln!("step {}, error {:.6}", step, error);
+            write_params(params_path, &params)?;
+        } else {
+            step /= 2;
+        }
+    }
+
+    eprintln!("done, error {:.6}", error);
+    write_params(params_path, &params)
+}
+
+// the parameters in `path`, or the defaults if there is no such file yet.
+// any other error is returned, so a profile that couldn't be read is never
+// overwritten.
+fn read_params(path: &str) -> Result<EvalParams, String> {
+    match fs::read_to_string(path) {
+        Ok(text) => text.parse::<EvalParams>(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(EvalParams::default()),
+        Err(e) => Err(format!("could not read {}: {}", path, e)),
+    }
+}
+
+fn write_params(path: &str, params: &EvalParams) -> Result<(), String> {
+    fs::write(path, params.to_string()).map_err(|e| format!("could not write {}: {}", path, e))
+}
+
+// the quiet positions in a positions file, with the results of their games.
+fn parse_positions(text: &str) -> Result<Vec<(Board, f64)>, String> {
+    let mut positions = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (board, result) =
+            parse_line(line).map_err(|e| format!("line {}: {}", number + 1, e))?;
+        if is_quiet(&board) {
+            positions.push((board, result));
+        }
+    }
+    Ok(positions)
+}
+
+fn parse_line(line: &str) -> Result<(Board, f64), String> {
+    let mut words = line.rsplitn(2, char::is_whitespace);
+    let result = words.next().unwrap_or_default();
+    let fen = words
+        .next()
+        .ok_or("expected a FEN and a result")?
+        .trim_end();
+    let fen = fen.strip_suffix("c9").unwrap_or(fen);
+    let result = parse_result(result).ok_or_else(|| format!("invalid result `{}`", result))?;
+    Ok((parse_fen(fen.trim())?, result))
+}
+
+// the score of a game for white.
+fn parse_result(word: &str) -> Option<f64> {
+    match word.trim_matches(|c| matches!(c, '[' | ']' | '"' | ';')) {
+        "1-0" => Some(1.0),
+        "0-1" => Some(0.0),
+        "1/2-1/2" => Some(0.5),
+        number => number
+            .parse::<f64>()
+            .ok()
+            .filter(|result| (0.0..=1.0).contains(result)),
+    }
+}
+
+fn is_quiet(board: &Board) -> bool {
+    !board.is_in_check(board.get_turn_color()) && board.get_legal_captures().next().is_none()
+}
+
+// the (field, value) indices of the weights to tune, in the order of
+// `EvalParams::fields_mut`. with no names, every weight is tuned.
+fn select_weights(
+    params: &mut EvalParams,
+    names: &[String],
+) -> Result<Vec<(usize, usize)>, String> {
+    let fields = params.fields_mut();
+    for name in names {
+        if !fields.iter().any(|(field, _)| field == name) {
+            return Err(format!("unknown parameter {}", name));
+        }
+    }
+    Ok(fields
+        .iter()
+        .enumerate()
+        .filter(|(_, (field, _))| names.is_empty() || names.contains(field))
+        .flat_map(|(index, (_, values))| (0..values.len()).map(move |value| (index, value)))
+        .collect())
+}
+
+// the expected score for white of a board evaluated at `eval` centipawns.
+fn expected_score(eval: i32, scale: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-scale * eval as f64 / 400.0))
+}
+
+// the mean squared difference between the results of the games and the
+// scores expected from the evaluation.
+fn mean_error(params: &EvalParams, positions: &[(Board, f64)], scale: f64) -> f64 {
+    positions
+        .iter()
+        .map(|(board, result)| {
+            let difference = result - expected_score(board.evaluate_with(params, WHITE), scale);
+            difference * difference
+        })
+        .sum::<f64>()
+        / positions.len() as f64
+}
+
+// the scale of the evaluation that predicts the results best before any
+// weights are changed, so tuning doesn't just stretch every weight.
+fn fit_scale(params: &EvalParams, positions: &[(Board, f64)]) -> f64 {
+    let mut scale = 1.0;
+    let mut error = mean_error(params, positions, scale);
+    let mut step = 0.5;
+    while step > 0.001 {
+        let mut improved = false;
+        for &candidate in [scale - step, scale + step].iter() {
+            let candidate_error = mean_error(params, positions, candidate);
+            if candidate > 0.0 && candidate <= MAX_SCALE && candidate_error < error {
+                scale = candidate;
+                error = candidate_error;
+                improved = true;
+                break;
+            }
+        }
+        if !improved {
+            step /= 2.0;
+        }
+    }
+    scale
+}
+
+// try moving each weight up and then down by `step`, keeping every change
+// that lowers the error, and return the error after the pass.
+fn tune_pass(
+    params: &mut EvalParams,
+    positions: &[(Board, f64)],
+    scale: f64,
+    weights: &[(usize, usize)],
+    step: i32,
+    mut error: f64,
+) -> f64 {
+    for &weight in weights {
+        for &delta in [step, -step].iter() {
+            adjust(params, weight, delta);
+            let adjusted = mean_error(params, positions, scale);
+            if adjusted < error {
+                error = adjusted;
+                break;
+            }
+            adjust(params, weight, -delta);
+        }
+    }
+    error
+}
+
+fn adjust(params: &mut EvalParams, (field, value): (usize, usize), delta: i32) {
+    *params.fields_mut()[field].1[value] += delta;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_positions() {
+        let text = "# positions from a few games\n\
+                    rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1 [0.5]\n\
+                    \n\
+                    4k3/8/8/8/8/8/8/3QK3 w - - 0 1 1-0\n\
+                    4k3/8/8/8/8/8/8/3QK3 b - - c9 \"0-1\";\n\
+                    4k3/8/8/8/8/8/3q4/3QK3 w - - 0 1 1/2-1/2\n";
+        let positions = parse_positions(text).unwrap();
+        // the last position isn't quiet, since the queens can be taken
+        assert_eq!(
+            positions
+                .iter()
+                .map(|(_, result)| *result)
+                .collect::<Vec<_>>(),
+            vec![0.5, 1.0, 0.0]
+        );
+        assert_eq!(
+            positions[1].0,
+            parse_fen("4k3/8/8/8/8/8/8/3QK3 w - - 0 1").unwrap()
+        );
+
+        assert_eq!(
+            parse_positions("4k3/8/8/8/8/8/8/3QK3 w - - 0 1 2-0"),
+            Err(String::from("line 1: invalid result `2-0`"))
+        );
+        assert!(parse_positions("1-0").is_err());
+    }
+
+    #[test]
+    fn test_read_params() {
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("chess-tune-{}.txt", std::process::id()));
+        let path = path.to_str().unwrap();
+        assert_eq!(read_params(path), Ok(EvalParams::default()));
+
+        fs::write(path, "pawn_shield 25\n").unwrap();
+        assert_eq!(read_params(path).unwrap().pawn_shield, 25);
+        fs::remove_file(path).unwrap();
+
+        // a path that can't be read isn't taken for a missing profile
+        assert!(read_params(dir.to_str().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_tune_pass() {
+        // white wins every game a knight up, but knights are worth nothing
+        let positions = parse_positions(
+            "4k3/8/8/8/8/8/8/1N2K3 w - - 0 1 1-0\n\
+             4k3/pp6/8/8/8/8/PP6/1N2K3 b - - 0 1 1-0\n\
+             4k3/pp6/8/8/8/8/PP6/4K3 w - - 0 1 1/2-1/2\n",
+        )
+        .unwrap();
+        let mut params = EvalParams::default();
+        params.material[4] = 0;
+        let weights = select_weights(&mut params.clone(), &[String::from("material")]).unwrap();
+        assert_eq!(weights, (0..6).map(|value| (0, value)).collect::<Vec<_>>());
+
+        let scale = fit_scale(&params, &positions);
+        let error = mean_error(&params, &positions, scale);
+        let tuned = tune_pass(&mut params, &positions, scale, &weights, FIRST_STEP, error);
+        assert!(tuned < error);
+        assert_eq!(params.material[4], FIRST_STEP);
+        assert_eq!(mean_error(&params, &positions, scale), tuned);
+
+        assert_eq!(
+            select_weights(&mut params, &[String::from("knight")]),
+            Err(String::from("unknown parameter knight"))
+        );
+    }
+}
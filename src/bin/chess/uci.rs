@@ -0,0 +1,371 @@
+// Universal Chess Interface front-end, for GUIs like Cute Chess or Arena
+//
+// the protocol is line based over stdin/stdout. searches run on a separate
+// thread so that `stop`, `isready` and `quit` are answered while thinking.
+use crate::search::{Hash, Limits, Search, DEFAULT_HASH_MB, MAX_DEPTH};
+use chess_engine::*;
+use std::{
+    io::{stdin, BufRead},
+    time::{Duration, Instant},
+};
+
+// kept back from the clock for GUI and process overhead
+pub const MOVE_OVERHEAD: Duration = Duration::from_millis(50);
+
+// format a move in the long algebraic notation used by uci, e.g. "e7e8q"
+pub fn format_uci_move(board: &Board, m: Move) -> String {
+    let color = board.get_turn_color();
+    match m {
+        Move::Piece(from, to) => {
+            let promotes = matches!(board.get_piece(from), Some(Piece::Pawn(_, _)))
+                && to.get_row() == if color == WHITE { 7 } else { 0 };
+            format!("{}{}{}", from, to, if promotes { "q" } else { "" })
+        }
+        Move::Promotion(from, to, piece) => format!(
+            "{}{}{}",
+            from,
+            to,
+            piece.get_san_letter().to_ascii_lowercase()
+        ),
+        Move::KingSideCastle | Move::QueenSideCastle => match board.get_king_pos(color) {
+            Some(king) if m == Move::KingSideCastle => {
+                format!("{}{}", king, king.next_right().next_right())
+            }
+            Some(king) => format!("{}{}", king, king.next_left().next_left()),
+            None => String::from("0000"),
+        },
+        Move::Resign => String::from("0000"),
+    }
+}
+
+// format the moves of `line`, played in order from `board`
+pub fn format_uci_line(board: &Board, line: &[Move]) -> String {
+    let mut board = *board;
+    let mut moves = vec![];
+    for &m in line {
+        moves.push(format_uci_move(&board, m));
+        board = board.apply_eval_move(m);
+    }
+    moves.join(" ")
+}
+
+// most lines the `MultiPV` option allows
+const MAX_MULTIPV: usize = 256;
+
+// format a search result as an `info` line, numbered when there are
+// several lines
+fn format_info(board: &Board, result: &SearchResult, multipv: Option<usize>) -> String {
+    let millis = result.elapsed.as_millis() as u64;
+    let score = match result.score {
+        Score::Centipawns(centipawns) => format!("cp {}", centipawns),
+        Score::Mate(moves) => format!("mate {}", moves),
+    };
+    format!(
+        "info depth {} seldepth {}{} score {} nodes {} nps {} time {} pv {}",
+        result.depth,
+        result.seldepth,
+        multipv.map_or(String::new(), |rank| format!(" multipv {}", rank)),
+        score,
+        result.nodes,
+        result.nodes * 1000 / millis.max(1),
+        millis,
+        format_uci_line(board, &result.pv)
+    )
+}
+
+// find the legal move written in long algebraic notation
+pub fn parse_uci_move(board: &Board, text: &str) -> Option<Move> {
+    let text = text.to_ascii_lowercase();
+    board
+        .get_legal_moves()
+        .find(|&m| format_uci_move(board, m) == text)
+}
+
+// parse "startpos|fen <fen> [moves ...]", returning the final position and
+// every position before it
+pub fn parse_position(args: &[&str]) -> Result<(Board, Vec<Board>), String> {
+    let moves_index = args
+        .iter()
+        .position(|&arg| arg == "moves")
+        .unwrap_or(args.len());
+    let mut board = match args.first() {
+        Some(&"startpos") => Board::default(),
+        Some(&"fen") => parse_fen(&args[1..moves_index].join(" "))?,
+        _ => return Err(String::from("expected startpos or fen")),
+    };
+
+    let mut history = vec![];
+    for &text in args.iter().skip(moves_index + 1) {
+        match parse_uci_move(&board, text) {
+            Some(m) => {
+                history.push(board);
+                board = board.apply_eval_move(m);
+            }
+            None => return Err(format!("illegal move {}", text)),
+        }
+    }
+    Ok((board, history))
+}
+
+// split "name <name> [value <value>]" from `setoption`, the name and value
+// may contain spaces
+fn parse_setoption(args: &[&str]) -> Option<(String, String)> {
+    let value_index = args
+        .iter()
+        .position(|&arg| arg == "value")
+        .unwrap_or(args.len());
+    match args.first() {
+        Some(&"name") => Some((
+            args[1..value_index].join(" "),
+            args.get(value_index + 1..).unwrap_or_default().join(" "),
+        )),
+        _ => None,
+    }
+}
+
+// limits given with `go`
+#[derive(Default)]
+struct GoOptions {
+    depth: Option<u8>,
+    nodes: Option<u64>,
+    movetime: Option<Duration>,
+    infinite: bool,
+    time: [Option<Duration>; 2],
+    increment: [Option<Duration>; 2],
+    movestogo: Option<u32>,
+}
+
+impl GoOptions {
+    fn parse(args: &[&str]) -> Self {
+        let mut options = GoOptions::default();
+        let mut args = args.iter();
+        while let Some(&arg) = args.next() {
+            let mut number = || args.next().and_then(|value| value.parse::<u64>().ok());
+            match arg {
+                "depth" => options.depth = number().map(|depth| depth.clamp(1, 255) as u8),
+                "nodes" => options.nodes = number(),
+                "movetime" => options.movetime = number().map(Duration::from_millis),
+                "wtime" => options.time[0] = number().map(Duration::from_millis),
+                "btime" => options.time[1] = number().map(Duration::from_millis),
+                "winc" => options.increment[0] = number().map(Duration::from_millis),
+                "binc" => options.increment[1] = number().map(Duration::from_millis),
+                "movestogo" => options.movestogo = number().map(|moves| moves.max(1) as u32),
+                "infinite" => options.infinite = true,
+                _ => {}
+            }
+        }
+        options
+    }
+
+    // time to spend on this move, None to search until depth or `stop`
+    fn budget(&self, color: Color) -> Option<Duration> {
+        if self.infinite {
+            return None;
+        }
+        if self.movetime.is_some() {
+            return self.movetime;
+        }
+
+        let side = if color == WHITE { 0 } else { 1 };
+        let time = self.time[side]?;
+        let increment = self.increment[side].unwrap_or_default();
+        let share = time / self.movestogo.unwrap_or(30) + increment / 2;
+        Some(share.min(time.saturating_sub(MOVE_OVERHEAD)))
+    }
+}
+
+pub fn run() -> Result<(), String> {
+    let mut board = Board::default();
+    let mut history = vec![];
+    let mut hash = Hash::new(DEFAULT_HASH_MB);
+    let mut multipv = 1;
+    let mut search: Option<Search> = None;
+
+    for line in stdin().lock().lines() {
+        let line = line.map_err(|e| e.to_string())?;
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (command, args) = match words.split_first() {
+            Some((command, args)) => (*command, args),
+            None => continue,
+        };
+
+        match command {
+            "uci" => {
+                println!("id name chess-engine {}", env!("CARGO_PKG_VERSION"));
+                println!("id author {}", env!("CARGO_PKG_AUTHORS").replace(':', ", "));
+                println!(
+                    "option name Hash type spin default {} min 1 max 1024",
+                    DEFAULT_HASH_MB
+                );
+                println!(
+                    "option name MultiPV type spin default 1 min 1 max {}",
+                    MAX_MULTIPV
+                );
+                println!("uciok");
+            }
+            "isready" => println!("readyok"),
+            "setoption" => match parse_setoption(args) {
+                Some((name, value)) if name.eq_ignore_ascii_case("hash") => {
+                    match value.parse::<usize>() {
+                        Ok(megabytes) => {
+                            if let Some(search) = search.take() {
+                                search.stop();
+                            }
+                            hash.resize(megabytes.clamp(1, 1024));
+                        }
+                        Err(_) => println!("info string invalid hash size {}", value),
+                    }
+                }
+                Some((name, value)) if name.eq_ignore_ascii_case("multipv") => {
+                    match value.parse::<usize>() {
+                        Ok(lines) => multipv = lines.clamp(1, MAX_MULTIPV),
+                        Err(_) => println!("info string invalid multipv {}", value),
+                    }
+                }
+                Some((name, _)) => println!("info string unknown option {}", name),
+                None => println!("info string expected setoption name <name> value <value>"),
+            },
+            "ucinewgame" => {
+                if let Some(search) = search.take() {
+                    search.stop();
+                }
+                hash.clear();
+                board = Board::default();
+                history.clear();
+            }
+            "position" => {
+                if let Some(search) = search.take() {
+                    search.stop();
+                }
+                match parse_position(args) {
+                    Ok((position, positions)) => {
+                        board = position;
+                        history = positions;
+                    }
+                    Err(e) => println!("info string {}", e),
+                }
+            }
+            "go" => {
+                if let Some(search) = search.take() {
+                    search.stop();
+                }
+                let options = GoOptions::parse(args);
+                let limits = Limits {
+                    depth: options.depth.unwrap_or(MAX_DEPTH),
+                    nodes: options.nodes,
+                    deadline: options
+                        .budget(board.get_turn_color())
+                        .map(|budget| Instant::now() + budget),
+                    infinite: options.infinite,
+                };
+                search = Some(Search::start(
+                    board,
+                    history.clone(),
+                    &hash,
+                    multipv,
+                    limits,
+                    move |lines: &[SearchResult]| {
+                        for (rank, line) in lines.iter().enumerate() {
+                            let rank = if multipv > 1 { Some(rank + 1) } else { None };
+                            println!("{}", format_info(&board, line, rank));
+                        }
+                    },
+                    move |best_move| println!("bestmove {}", format_uci_move(&board, best_move)),
+                ));
+            }
+            "stop" => {
+                if let Some(search) = search.take() {
+                    search.stop();
+                }
+            }
+            "quit" => break,
+            // debug, register and ponderhit need no response
+            _ => {}
+        }
+    }
+
+    if let Some(search) = search.take() {
+        search.stop();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uci_moves() {
+        let (board, history) = parse_position(&[
+            "startpos", "moves", "e2e4", "e7e5", "g1f3", "b8c6", "f1c4", "g8f6",
+        ])
+        .unwrap();
+        assert_eq!(history.len(), 6);
+        assert_eq!(parse_uci_move(&board, "e1g1"), Some(Move::KingSideCastle));
+        assert_eq!(
+            format_uci_move(&board, Move::KingSideCastle),
+            String::from("e1g1")
+        );
+        assert_eq!(parse_uci_move(&board, "e1c1"), None);
+
+        let (board, _) =
+            parse_position(&["fen", "8/P6k/8/8/8/8/8/K7", "w", "-", "-", "0", "1"]).unwrap();
+        let promotion = parse_uci_move(&board, "a7a8q").unwrap();
+        assert_eq!(format_uci_move(&board, promotion), "a7a8q");
+
+        assert!(parse_position(&["startpos", "moves", "e2e5"]).is_err());
+    }
+
+    #[test]
+    fn test_info() {
+        let board = Board::default();
+        let result = SearchResult {
+            best_move: Move::Piece(E2, E4),
+            pv: vec![Move::Piece(E2, E4), Move::Piece(E7, E5)],
+            depth: 2,
+            seldepth: 3,
+            nodes: 500,
+            score: Score::Centipawns(25),
+            elapsed: Duration::from_millis(250),
+        };
+        assert_eq!(
+            format_info(&board, &result, None),
+            "info depth 2 seldepth 3 score cp 25 nodes 500 nps 2000 time 250 pv e2e4 e7e5"
+        );
+        let result = SearchResult {
+            score: Score::Mate(-3),
+            ..result
+        };
+        assert!(format_info(&board, &result, Some(2)).contains("multipv 2 score mate -3"));
+    }
+
+    #[test]
+    fn test_go_budget() {
+        let options = GoOptions::parse(&["wtime", "60000", "btime", "1000", "winc", "2000"]);
+        assert_eq!(options.budget(WHITE), Some(Duration::from_millis(3000)));
+        assert_eq!(
+            options.budget(BLACK),
+            Some(Duration::from_millis(1000) / 30)
+        );
+        assert_eq!(
+            GoOptions::parse(&["movetime", "500"]).budget(WHITE),
+            Some(Duration::from_millis(500))
+        );
+        assert_eq!(GoOptions::parse(&["infinite"]).budget(WHITE), None);
+        assert_eq!(GoOptions::parse(&["depth", "3"]).depth, Some(3));
+        assert_eq!(GoOptions::parse(&["nodes", "5000"]).nodes, Some(5000));
+    }
+
+    #[test]
+    fn test_setoption() {
+        assert_eq!(
+            parse_setoption(&["name", "Hash", "value", "64"]),
+            Some((String::from("Hash"), String::from("64")))
+        );
+        assert_eq!(
+            parse_setoption(&["name", "Clear", "Hash"]),
+            Some((String::from("Clear Hash"), String::new()))
+        );
+        assert_eq!(parse_setoption(&["Hash", "64"]), None);
+    }
+}
@@ -0,0 +1,425 @@
+// Chess Engine Communication Protocol front-end, for XBoard and WinBoard
+//
+// stdin is read on a separate thread, so that commands like `?` and
+// `force` are handled while the engine is thinking.
+use crate::search::{Hash, Limits, Search, DEFAULT_HASH_MB, MAX_DEPTH};
+use crate::uci::{format_uci_move, parse_uci_move, MOVE_OVERHEAD};
+use chess_engine::*;
+use std::{
+    io::{stdin, BufRead},
+    sync::mpsc::{channel, RecvTimeoutError},
+    thread,
+    time::{Duration, Instant},
+};
+
+// how often a running search is checked for its move
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+// clock settings from `level` and `st`
+#[derive(Debug, PartialEq)]
+struct TimeControl {
+    // moves per session, 0 for the whole game
+    moves_per_session: u32,
+    base: Duration,
+    increment: Duration,
+    // fixed time per move, set with `st`
+    per_move: Option<Duration>,
+}
+
+impl Default for TimeControl {
+    fn default() -> Self {
+        TimeControl {
+            moves_per_session: 40,
+            base: Duration::from_secs(300),
+            increment: Duration::ZERO,
+            per_move: None,
+        }
+    }
+}
+
+impl TimeControl {
+    // parse `level MPS BASE INC`, where BASE is minutes or minutes:seconds
+    fn parse_level(args: &[&str]) -> Option<Self> {
+        let (moves, base, increment) = match args {
+            [moves, base, increment] => (moves, base, increment),
+            _ => return None,
+        };
+        let base = match base.split_once(':') {
+            Some((minutes, seconds)) => {
+                minutes.parse::<u64>().ok()? * 60 + seconds.parse::<u64>().ok()?
+            }
+            None => base.parse::<u64>().ok()? * 60,
+        };
+        Some(TimeControl {
+            moves_per_session: moves.parse().ok()?,
+            base: Duration::from_secs(base),
+            increment: Duration::from_secs_f64(increment.parse::<f64>().ok()?.max(0.0)),
+            per_move: None,
+        })
+    }
+
+    // time to spend on the move at `fullmove_number`, given the time left
+    fn budget(&self, time_left: Option<Duration>, fullmove_number: u16) -> Duration {
+        if let Some(per_move) = self.per_move {
+            return per_move;
+        }
+        let time_left = time_left.unwrap_or(self.base);
+        let moves_to_go = match self.moves_per_session {
+            0 => 30,
+            moves => moves - (fullmove_number.max(1) as u32 - 1) % moves,
+        };
+        let share = time_left / moves_to_go + self.increment / 2;
+        share.min(time_left.saturating_sub(MOVE_OVERHEAD))
+    }
+}
+
+// format a search result as a thinking output line
+fn format_thinking(board: &Board, result: &SearchResult) -> String {
+    // mates are reported as 100000 + moves
+    let score = match result.score {
+        Score::Centipawns(centipawns) => centipawns,
+        Score::Mate(moves) if moves >= 0 => 100000 + moves,
+        Score::Mate(moves) => -100000 + moves,
+    };
+    let mut board = *board;
+    let mut line = vec![];
+    for &m in &result.pv {
+        line.push(board.to_san(m));
+        board = board.apply_eval_move(m);
+    }
+    format!(
+        "{} {} {} {} {}",
+        result.depth,
+        score,
+        result.elapsed.as_millis() / 10,
+        result.nodes,
+        line.join(" ")
+    )
+}
+
+// result line for a game that ended with `m`, if it did
+fn game_over(before: &Board, m: Move) -> Option<&'static str> {
+    match before.play_move(m) {
+        GameResult::Victory(WHITE) => Some("1-0 {White mates}"),
+        GameResult::Victory(BLACK) => Some("0-1 {Black mates}"),
+        GameResult::Stalemate => Some("1/2-1/2 {Stalemate}"),
+        _ => None,
+    }
+}
+
+struct Engine {
+    board: Board,
+    // positions before the current one, for undo and repetition
+    history: Vec<Board>,
+    // side the engine plays, None in force mode
+    engine_color: Option<Color>,
+    max_depth: u8,
+    time_control: TimeControl,
+    // engine's clock, set with `time`
+    time_left: Option<Duration>,
+    // print thinking output
+    post: bool,
+    // transposition table, sized with `memory`
+    hash: Hash,
+    search: Option<Search>,
+}
+
+impl Engine {
+    fn new() -> Self {
+        Engine {
+            board: Board::default(),
+            history: vec![],
+            engine_color: Some(BLACK),
+            max_depth: MAX_DEPTH,
+            time_control: TimeControl::default(),
+            time_left: None,
+            post: false,
+            hash: Hash::new(DEFAULT_HASH_MB),
+            search: None,
+        }
+    }
+
+    // stop thinking without playing the move
+    fn abort_search(&mut self) {
+        if let Some(search) = self.search.take() {
+            search.stop();
+        }
+    }
+
+    fn start_search(&mut self) {
+        let budget = self
+            .time_control
+            .budget(self.time_left, self.board.get_fullmove_number());
+        let limits = Limits {
+            depth: self.max_depth,
+            nodes: None,
+            deadline: Some(Instant::now() + budget),
+            infinite: false,
+        };
+
+        let board = self.board;
+        let post = self.post;
+        self.search = Some(Search::start(
+            board,
+            self.history.clone(),
+            &self.hash,
+            1,
+            limits,
+            move |lines: &[SearchResult]| {
+                if post {
+                    println!("{}", format_thinking(&board, &lines[0]));
+                }
+            },
+            |_| {},
+        ));
+    }
+
+    // play the move found by a search
+    fn play_engine_move(&mut self, m: Move) {
+        if m == Move::Resign {
+            println!("resign");
+            self.engine_color = None;
+            return;
+        }
+
+        println!("move {}", format_uci_move(&self.board, m));
+        self.play(m);
+    }
+
+    fn play(&mut self, m: Move) {
+        if let Some(result) = game_over(&self.board, m) {
+            println!("{}", result);
+        }
+        self.history.push(self.board);
+        self.board = self.board.apply_eval_move(m);
+    }
+
+    fn user_move(&mut self, text: &str) {
+        self.abort_search();
+        let m = match parse_uci_move(&self.board, text) {
+            Some(m) => m,
+            None => {
+                println!("Illegal move: {}", text);
+                return;
+            }
+        };
+
+        let over = game_over(&self.board, m).is_some();
+        self.play(m);
+        if !over && self.engine_color == Some(self.board.get_turn_color()) {
+            self.start_search();
+        }
+    }
+
+    fn take_back(&mut self, moves: usize) {
+        self.abort_search();
+        for _ in 0..moves {
+            if let Some(board) = self.history.pop() {
+                self.board = board;
+            }
+        }
+    }
+
+    // handle a command, returns false on `quit`
+    fn handle(&mut self, line: &str) -> bool {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (command, args) = match words.split_first() {
+            Some((command, args)) => (*command, args),
+            None => return true,
+        };
+
+        match command {
+            "protover" => println!(
+                "feature myname=\"chess-engine {}\" usermove=1 setboard=1 ping=1 \
+                 playother=1 memory=1 san=0 sigint=0 sigterm=0 colors=0 analyze=0 \
+                 variants=\"normal,horde\" done=1",
+                env!("CARGO_PKG_VERSION")
+            ),
+            "new" => {
+                self.abort_search();
+                self.hash.clear();
+                self.board = Board::default();
+                self.history.clear();
+                self.engine_color = Some(BLACK);
+                self.max_depth = MAX_DEPTH;
+                self.time_left = None;
+            }
+            "variant" => {
+                self.abort_search();
+                self.board = match args.first() {
+                    Some(&"normal") => Board::default(),
+                    Some(&"horde") => Board::horde(),
+                    _ => {
+                        println!("Error (unsupported variant): {}", args.join(" "));
+                        return true;
+                    }
+                };
+                self.history.clear();
+            }
+            "force" => {
+                self.abort_search();
+                self.engine_color = None;
+            }
+            "go" => {
+                self.abort_search();
+                self.engine_color = Some(self.board.get_turn_color());
+                self.start_search();
+            }
+            "playother" => {
+                self.abort_search();
+                self.engine_color = Some(!self.board.get_turn_color());
+            }
+            "usermove" => match args.first() {
+                Some(text) => self.user_move(text),
+                None => println!("Error (missing move): usermove"),
+            },
+            "?" => {
+                if let Some(search) = self.search.take() {
+                    self.play_engine_move(search.stop());
+                }
+            }
+            "level" => match TimeControl::parse_level(args) {
+                Some(time_control) => self.time_control = time_control,
+                None => println!("Error (invalid level): {}", args.join(" ")),
+            },
+            "st" => match args.first().and_then(|seconds| seconds.parse::<f64>().ok()) {
+                Some(seconds) if seconds > 0.0 => {
+                    self.time_control.per_move = Some(Duration::from_secs_f64(seconds))
+                }
+                _ => println!("Error (invalid time): {}", args.join(" ")),
+            },
+            "sd" => match args.first().and_then(|depth| depth.parse::<u8>().ok()) {
+                Some(depth) if depth > 0 => self.max_depth = depth,
+                _ => println!("Error (invalid depth): {}", args.join(" ")),
+            },
+            "memory" => match args
+                .first()
+                .and_then(|megabytes| megabytes.parse::<usize>().ok())
+            {
+                Some(megabytes) if megabytes > 0 => {
+                    self.abort_search();
+                    self.hash.resize(megabytes);
+                }
+                _ => println!("Error (invalid memory): {}", args.join(" ")),
+            },
+            "time" => {
+                self.time_left = args
+                    .first()
+                    .and_then(|centiseconds| centiseconds.parse::<u64>().ok())
+                    .map(|centiseconds| Duration::from_millis(centiseconds * 10))
+            }
+            "setboard" => {
+                self.abort_search();
+                match parse_fen(&args.join(" ")) {
+                    Ok(board) => {
+                        self.board = board;
+                        self.history.clear();
+                    }
+                    Err(_) => println!("tellusererror Illegal position"),
+                }
+            }
+            "undo" => self.take_back(1),
+            "remove" => self.take_back(2),
+            "result" => {
+                self.abort_search();
+                self.engine_color = None;
+            }
+            "ping" => println!("pong {}", args.join(" ")),
+            "post" => self.post = true,
+            "nopost" => self.post = false,
+            "quit" => return false,
+            // no response needed
+            "xboard" | "accepted" | "rejected" | "random" | "easy" | "hard" | "otim"
+            | "computer" | "name" | "rating" | "ics" | "white" | "black" => {}
+            // protocol version 1 sends moves without usermove
+            text if parse_uci_move(&self.board, text).is_some() => self.user_move(text),
+            _ => println!("Error (unknown command): {}", command),
+        }
+        true
+    }
+}
+
+pub fn run() -> Result<(), String> {
+    let (sender, lines) = channel();
+    thread::spawn(move || {
+        for line in stdin().lock().lines().map_while(Result::ok) {
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut engine = Engine::new();
+    loop {
+        if engine.search.as_ref().map_or(false, Search::is_finished) {
+            if let Some(search) = engine.search.take() {
+                engine.play_engine_move(search.join());
+            }
+        }
+
+        match lines.recv_timeout(POLL_INTERVAL) {
+            Ok(line) => {
+                if !engine.handle(&line) {
+                    break;
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+
+    engine.abort_search();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_level() {
+        let level = TimeControl::parse_level(&["40", "5", "0"]).unwrap();
+        assert_eq!(level.base, Duration::from_secs(300));
+        assert_eq!(level.budget(None, 1), Duration::from_millis(7500));
+        assert_eq!(
+            level.budget(Some(Duration::from_secs(10)), 40),
+            Duration::from_secs(10) - MOVE_OVERHEAD
+        );
+
+        let level = TimeControl::parse_level(&["0", "2:30", "1.5"]).unwrap();
+        assert_eq!(level.base, Duration::from_secs(150));
+        assert_eq!(level.increment, Duration::from_millis(1500));
+        assert_eq!(TimeControl::parse_level(&["40", "5"]), None);
+    }
+
+    #[test]
+    fn test_xboard_moves() {
+        let mut engine = Engine::new();
+        assert!(engine.handle("force"));
+        for text in ["usermove f2f3", "usermove e7e5", "usermove g2g4"] {
+            engine.handle(text);
+        }
+        assert_eq!(engine.history.len(), 3);
+        engine.handle("usermove d8h4");
+        assert!(engine.board.is_checkmate());
+
+        engine.handle("remove");
+        assert_eq!(engine.history.len(), 2);
+        engine.handle("usermove e1e2");
+        assert_eq!(engine.history.len(), 2);
+
+        engine.handle("variant horde");
+        engine.handle("new");
+        assert_eq!(engine.board, Board::default());
+        engine.handle("variant horde");
+        assert_eq!(engine.board, Board::horde());
+        engine.handle("force");
+        engine.handle("setboard 4k3/8/8/8/8/8/4P3/4K3 w - - 0 1");
+        assert_eq!(engine.board.get_piece(E2), Some(Piece::Pawn(WHITE, E2)));
+        assert!(engine.handle("sd 2"));
+        assert_eq!(engine.max_depth, 2);
+        engine.handle("memory 64");
+        assert_eq!(engine.hash.get_megabytes(), 64);
+        assert!(!engine.handle("quit"));
+    }
+}
@@ -1,4 +1,7 @@
 use super::*;
+use crate::mobility::{king_safety, mobility};
+use crate::pawns::{blockade, evaluate_pawns, square_bit, PawnTable};
+use crate::search::{mate_in, INFINITY, MATE};
 use alloc::string::{String, ToString};
 use core::cmp::Ordering;
 use either::Either;
@@ -33,7 +36,7 @@ impl BoardBuilder {
         }
 
         for _ in 0..8 {
-            *self.board.get_square(pos) = Square::from(piece.move_to(pos));
+            self.board.set_square(pos, Square::from(piece.move_to(pos)));
             pos = pos.next_right();
         }
 
@@ -47,7 +50,7 @@ impl BoardBuilder {
         }
 
         for _ in 0..8 {
-            *self.board.get_square(pos) = Square::from(piece.move_to(pos));
+            self.board.set_square(pos, Square::from(piece.move_to(pos)));
             pos = pos.next_above();
         }
 
@@ -56,7 +59,7 @@ impl BoardBuilder {
 
     pub fn piece(mut self, piece: Piece) -> Self {
         let pos = piece.get_pos();
-        *self.board.get_square(pos) = Square::from(piece);
+        self.board.set_square(pos, Square::from(piece));
         self
     }
 
@@ -105,7 +108,7 @@ impl BoardBuilder {
     }
 
     pub fn set_en_passant(mut self, position: Option<Position>) -> Self {
-        self.board.en_passant = position;
+        self.board.set_en_passant(position);
         self
     }
 
@@ -114,7 +117,19 @@ impl BoardBuilder {
         self
     }
 
-    pub fn build(self) -> Board {
+    pub fn set_halfmove_clock(mut self, halfmove_clock: u16) -> Self {
+        self.board.halfmove_clock = halfmove_clock;
+        self
+    }
+
+    pub fn set_fullmove_number(mut self, fullmove_number: u16) -> Self {
+        self.board.fullmove_number = fullmove_number;
+        self
+    }
+
+    pub fn build(mut self) -> Board {
+        // castling rights are set directly, so hash from scratch
+        self.board.hash = self.board.compute_zobrist_hash();
         self.board
     }
 }
@@ -196,7 +211,26 @@ impl Default for Board {
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, BorshDeserialize, BorshSerialize)]
+/// A chess position, with the move counters needed to write it as FEN.
+///
+/// `==` and the ordering compare the position alone, as they did before the
+/// move counters were added, so the same position reached at different
+/// points of a game compares equal. Use `is_same_position` to check whether
+/// two boards count as the same position under the repetition rules.
+///
+/// ```rust
+/// use chess_engine::{parse_fen, Board};
+///
+/// let later = parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 4 3").unwrap();
+/// assert_eq!(later, Board::default());
+/// assert_ne!(later.get_fullmove_number(), Board::default().get_fullmove_number());
+/// ```
+///
+/// The Borsh encoding is the same as in version 0.1.2, and leaves out the
+/// move counters and the Zobrist hash. Reading a board back rebuilds the
+/// hash and starts the counters again from the start of a game, so store
+/// boards as FEN strings with `format_fen` where the counters matter.
+#[derive(Clone, Copy, Debug)]
 pub struct Board {
     squares: [Square; 64],
 
@@ -206,23 +240,174 @@ pub struct Board {
     black_castling_rights: CastlingRights,
 
     turn: Color,
+
+    halfmove_clock: u16,
+    fullmove_number: u16,
+
+    hash: u64,
+}
+
+impl PartialEq for Board {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Board {}
+
+impl PartialOrd for Board {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Board {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.squares
+            .cmp(&other.squares)
+            .then_with(|| self.en_passant.cmp(&other.en_passant))
+            .then_with(|| self.white_castling_rights.cmp(&other.white_castling_rights))
+            .then_with(|| self.black_castling_rights.cmp(&other.black_castling_rights))
+            .then_with(|| self.turn.cmp(&other.turn))
+    }
+}
+
+impl BorshSerialize for Board {
+    fn serialize<W: borsh::maybestd::io::Write>(
+        &self,
+        writer: &mut W,
+    ) -> borsh::maybestd::io::Result<()> {
+        self.squares.serialize(writer)?;
+        self.en_passant.serialize(writer)?;
+        self.white_castling_rights.serialize(writer)?;
+        self.black_castling_rights.serialize(writer)?;
+        self.turn.serialize(writer)
+    }
+}
+
+impl BorshDeserialize for Board {
+    fn deserialize(buf: &mut &[u8]) -> borsh::maybestd::io::Result<Self> {
+        Ok(Self {
+            squares: BorshDeserialize::deserialize(buf)?,
+            en_passant: BorshDeserialize::deserialize(buf)?,
+            white_castling_rights: BorshDeserialize::deserialize(buf)?,
+            black_castling_rights: BorshDeserialize::deserialize(buf)?,
+            turn: BorshDeserialize::deserialize(buf)?,
+
+            halfmove_clock: 0,
+            fullmove_number: 1,
+
+            hash: 0,
+        }
+        .with_zobrist_hash())
+    }
 }
 
 impl Board {
-    pub fn value_for(&self, ally_color: Color) -> f64 {
+    /// The material and positional value of the board for `ally_color`, in
+    /// centipawns, where a pawn is worth `PAWN_VALUE`.
+    ///
+    /// Each piece's middlegame and endgame values are blended by the game
+    /// phase, so that the king comes out and the pawns run for promotion
+    /// as the other pieces are traded off. Doubled, isolated and backward
+    /// pawns are penalized, and passed pawns are rewarded by how far they
+    /// have advanced, unless a piece blocks them. Pieces are rewarded for
+    /// the squares they control, and kings for the pawns sheltering them
+    /// and penalized for attacks on the squares around them.
+    pub fn value_for(&self, ally_color: Color) -> i32 {
+        self.evaluate_with(&EvalParams::DEFAULT, ally_color)
+    }
+
+    /// Like `value_for`, but weighing everything with `params` instead of
+    /// the default weights.
+    pub fn evaluate_with(&self, params: &EvalParams, ally_color: Color) -> i32 {
+        self.evaluate(params, ally_color, None)
+    }
+
+    /// Break the value of the board for `ally_color` down into the terms
+    /// of the evaluation, to see why it is valued the way it is.
+    pub fn eval_trace(&self, ally_color: Color) -> EvalTrace {
+        self.eval_trace_with(&EvalParams::DEFAULT, ally_color)
+    }
+
+    /// Like `eval_trace`, but weighing everything with `params`.
+    pub fn eval_trace_with(&self, params: &EvalParams, ally_color: Color) -> EvalTrace {
+        self.trace(params, ally_color, None)
+    }
+
+    /// Like `evaluate_with`, but looking up the pawn structure in
+    /// `pawn_table` when one is given.
+    pub(crate) fn evaluate(
+        &self,
+        params: &EvalParams,
+        ally_color: Color,
+        pawn_table: Option<&mut PawnTable>,
+    ) -> i32 {
+        self.trace(params, ally_color, pawn_table).get_total()
+    }
+
+    fn trace(
+        &self,
+        params: &EvalParams,
+        ally_color: Color,
+        pawn_table: Option<&mut PawnTable>,
+    ) -> EvalTrace {
+        let mut trace = EvalTrace::new(ally_color);
+        let (mut white_pawns, mut black_pawns, mut occupied, mut pawn_key) = (0, 0, 0, 0);
+        for piece in self.squares.iter().filter_map(|square| square.get_piece()) {
+            let color = piece.get_color();
+            let material = params.material[piece.get_kind_index()];
+            trace.material.add(color, (material, material));
+            trace.squares.add(
+                color,
+                (
+                    params.get_middlegame_value(piece) - material,
+                    params.get_endgame_value(piece) - material,
+                ),
+            );
+            trace.mobility.add(color, mobility(params, piece, self));
+            trace.phase += params.get_phase_weight(piece);
+
+            let bit = square_bit(piece.get_pos());
+            occupied |= bit;
+            if piece.is_pawn() {
+                pawn_key ^= zobrist::piece_key(piece);
+                match color {
+                    WHITE => white_pawns |= bit,
+                    BLACK => black_pawns |= bit,
+                }
+            }
+        }
+
+        let structure = match pawn_table {
+            Some(table) => table.get(params, pawn_key, white_pawns, black_pawns),
+            None => evaluate_pawns(params, white_pawns, black_pawns),
+        };
+        let blocked = blockade(params, &structure, white_pawns, occupied);
+        for (side, &color) in [WHITE, BLACK].iter().enumerate() {
+            trace.pawn_structure.add(color, structure.scores[side]);
+            trace.pawn_structure.add(color, blocked[side]);
+            trace
+                .king_safety
+                .add(color, (king_safety(params, self, color), 0));
+        }
+
+        trace.max_phase = params.get_max_phase();
+        trace.phase = trace.phase.min(trace.max_phase);
+        trace
+    }
+
+    /// How far the game is from the endgame, by the pieces left on the
+    /// board: `MAX_PHASE` with every knight, bishop, rook and queen, down
+    /// to 0 with only kings and pawns. Promotions can't raise it past
+    /// `MAX_PHASE`.
+    pub fn get_game_phase(&self) -> i32 {
         self.squares
             .iter()
-            .map(|square| match square.get_piece() {
-                Some(piece) => {
-                    if piece.get_color() == ally_color {
-                        piece.get_weighted_value()
-                    } else {
-                        -piece.get_weighted_value()
-                    }
-                }
-                None => 0.0,
-            })
-            .sum()
+            .filter_map(|square| square.get_piece())
+            .map(|piece| piece.get_phase_weight())
+            .sum::<i32>()
+            .min(MAX_PHASE)
     }
 
     #[inline]
@@ -253,6 +438,47 @@ impl Board {
             .flatten()
     }
 
+    /// Get the legal moves for the current player that capture a piece or
+    /// promote a pawn.
+    pub fn get_legal_captures(&self) -> impl Iterator<Item = Move> + '_ {
+        let color = self.get_current_player_color();
+        self.squares
+            .iter()
+            .filter_map(move |square| match square.get_piece() {
+                Some(piece) if piece.get_color() == color => Some(piece.get_legal_captures(self)),
+                _ => None,
+            })
+            .flatten()
+    }
+
+    /// Count the positions reached after exactly `depth` moves, trying
+    /// every legal move at each step.
+    ///
+    /// Comparing these counts against published results for well-known
+    /// positions is the standard way to validate move generation, including
+    /// castling, en passant and promotions.
+    pub fn perft(&self, depth: u8) -> u64 {
+        match depth {
+            0 => 1,
+            1 => self.get_legal_moves().count() as u64,
+            _ => self
+                .get_legal_moves()
+                .map(|m| self.apply_eval_move(m).perft(depth - 1))
+                .sum(),
+        }
+    }
+
+    /// Like `perft`, but split by the first move, which helps find the
+    /// move that differs from a reference engine.
+    pub fn perft_divide(&self, depth: u8) -> Vec<(Move, u64)> {
+        if depth == 0 {
+            return Vec::new();
+        }
+        self.get_legal_moves()
+            .map(|m| (m, self.apply_eval_move(m).perft(depth - 1)))
+            .collect()
+    }
+
     /// Get the best move for the current player with `depth` number of moves
     /// of lookahead.
     ///
@@ -263,38 +489,128 @@ impl Board {
     ///
     /// It's best not to use the rating value by itself for anything, as it
     /// is relative to the other player's move ratings as well.
-    pub fn get_best_next_move(&self, depth: u8) -> (Move, u64, f64) {
-        let legal_moves = self.get_legal_moves();
-        let mut best_move_value = -999999.0;
-        let mut best_move = Move::Resign;
+    pub fn get_best_next_move(&self, depth: u8) -> (Move, u64, i32) {
+        self.get_best_next_move_with_history(depth, &[])
+    }
 
-        let color = self.get_current_player_color();
+    /// Search for the best move with iterative deepening until `limits` are
+    /// reached, returning the line of play it expects and its score.
+    ///
+    /// This has no clock, so `movetime` and `deadline` are ignored; use a
+    /// `Searcher` to set one, or to cancel the search.
+    ///
+    /// Every call allocates a transposition table of `DEFAULT_TABLE_SIZE`
+    /// bytes. Where memory is tight, or to keep what the search learnt for
+    /// the next one, use `search_with_table`.
+    pub fn search(&self, limits: SearchLimits) -> SearchResult {
+        Searcher::default().search(self, limits, |_| {})
+    }
 
-        let mut board_count = 0;
-        for m in legal_moves {
-            let child_board_value = self.apply_eval_move(m).minimax(
-                Either::Left(depth),
-                -1000000.0,
-                1000000.0,
-                false,
-                color,
-                &mut board_count,
-            );
-            if child_board_value >= best_move_value {
-                best_move = m;
-                best_move_value = child_board_value;
-            }
-        }
+    /// Like `search`, but remembering searched positions in `table`.
+    pub fn search_with_table(
+        &self,
+        limits: SearchLimits,
+        table: &mut TranspositionTable,
+    ) -> SearchResult {
+        let owned = core::mem::replace(table, TranspositionTable::from_buffer(&mut []));
+        let mut searcher = Searcher::new(owned);
+        let result = searcher.search(self, limits, |_| {});
+        *table = searcher.into_table();
+        result
+    }
 
-        (best_move, board_count, best_move_value)
+    /// Find a forced checkmate for the current player in at most
+    /// `max_moves` of their own moves, trying every reply.
+    ///
+    /// This returns the shortest mate, with the longest defence against it,
+    /// or `None` if the other player can always avoid being mated that
+    /// quickly. Unlike the other searches, the answer doesn't depend on
+    /// the evaluation, so it can be used to check puzzle solutions.
+    pub fn find_mate(&self, max_moves: u8) -> Option<Vec<Move>> {
+        (1..=max_moves).find_map(|moves| mate_in(self, moves))
+    }
+
+    /// Like `search`, but finding the best `n` moves instead of one, each
+    /// with the line of play it leads to and its score, best first.
+    ///
+    /// Like `search`, every call allocates a transposition table of
+    /// `DEFAULT_TABLE_SIZE` bytes; use `analyse_multipv_with_table` to give
+    /// it one instead.
+    pub fn analyse_multipv(&self, limits: SearchLimits, n: usize) -> Vec<SearchResult> {
+        Searcher::default().search_multipv(self, limits, n, |_| {})
     }
 
-    pub fn get_next_move(&self, depths: &[u8], seed: [u8; 32]) -> (Move, u64, f64) {
+    /// Like `analyse_multipv`, but remembering searched positions in
+    /// `table`.
+    pub fn analyse_multipv_with_table(
+        &self,
+        limits: SearchLimits,
+        n: usize,
+        table: &mut TranspositionTable,
+    ) -> Vec<SearchResult> {
+        let owned = core::mem::replace(table, TranspositionTable::from_buffer(&mut []));
+        let mut searcher = Searcher::new(owned);
+        let results = searcher.search_multipv(self, limits, n, |_| {});
+        *table = searcher.into_table();
+        results
+    }
+
+    /// Get the best move for the current player with `depth` number of moves
+    /// of lookahead, given the positions that were played before this one.
+    ///
+    /// Any position in the search that repeats a position from `history`
+    /// (or from the line currently being searched) is scored as a draw, so
+    /// the AI neither walks into a repetition when it is winning nor misses
+    /// one when it is losing.
+    ///
+    /// Every call allocates a transposition table of `SMALL_TABLE_SIZE`
+    /// bytes; use `get_best_next_move_with_table` to give it one instead.
+    pub fn get_best_next_move_with_history(
+        &self,
+        depth: u8,
+        history: &[Board],
+    ) -> (Move, u64, i32) {
+        self.get_best_next_move_with_table(
+            depth,
+            history,
+            &mut TranspositionTable::new(SMALL_TABLE_SIZE),
+        )
+    }
+
+    /// Like `get_best_next_move_with_history`, but remembering searched
+    /// positions in `table`.
+    ///
+    /// Reusing a table between calls, for example when searching the same
+    /// position one move deeper, lets later searches start with the best
+    /// moves found by earlier ones.
+    pub fn get_best_next_move_with_table(
+        &self,
+        depth: u8,
+        history: &[Board],
+        table: &mut TranspositionTable,
+    ) -> (Move, u64, i32) {
+        let owned = core::mem::replace(table, TranspositionTable::from_buffer(&mut []));
+        let mut searcher = Searcher::new(owned).set_history(history);
+        let result = searcher.search_root(self, depth, &[]);
+        *table = searcher.into_table();
+        // nothing can cut a search without limits short
+        result.unwrap_or((Move::Resign, 0, -MATE))
+    }
+
+    /// Get a move for the current player, searching `depths[i]` moves
+    /// chosen at random at each ply `i`, or every move where it's 0.
+    ///
+    /// This returns the same values as `get_best_next_move`, and the same
+    /// `seed` always gives the same move.
+    pub fn get_next_move(&self, depths: &[u8], seed: [u8; 32]) -> (Move, u64, i32) {
         let mut rng = ChaCha20Rng::from_seed(seed);
-        let legal_moves = self
-            .get_legal_moves()
-            .choose_multiple(&mut rng, depths[0].into());
-        let mut best_move_value = -999999.0;
+        let legal_moves = match depths[0] {
+            0 => self.get_legal_moves().collect(),
+            max_moves => self
+                .get_legal_moves()
+                .choose_multiple(&mut rng, max_moves.into()),
+        };
+        let mut best_move_value = -MATE;
         let mut best_move = Move::Resign;
 
         let color = self.get_current_player_color();
@@ -303,8 +619,8 @@ impl Board {
         for m in legal_moves {
             let child_board_value = self.apply_eval_move(m).minimax(
                 Either::Right((&depths[1..], rng.clone())),
-                -1000000.0,
-                1000000.0,
+                -INFINITY,
+                INFINITY,
                 false,
                 color,
                 &mut board_count,
@@ -328,22 +644,25 @@ impl Board {
     ///
     /// It's best not to use the rating value by itself for anything, as it
     /// is relative to the other player's move ratings as well.
-    pub fn get_worst_next_move(&self, depth: u8) -> (Move, u64, f64) {
+    pub fn get_worst_next_move(&self, depth: u8) -> (Move, u64, i32) {
         let legal_moves = self.get_legal_moves();
-        let mut best_move_value = -999999.0;
+        let mut best_move_value = -MATE;
         let mut best_move = Move::Resign;
 
         let color = self.get_current_player_color();
 
+        // the searches after each move share their transpositions
+        let mut table = TranspositionTable::new(SMALL_TABLE_SIZE);
         let mut board_count = 0;
         for m in legal_moves {
-            let child_board_value = self.apply_eval_move(m).minimax(
+            let child_board_value = self.apply_eval_move(m).minimax_with_table(
                 Either::Left(depth),
-                -1000000.0,
-                1000000.0,
+                -INFINITY,
+                INFINITY,
                 true,
                 !color,
                 &mut board_count,
+                &mut table,
             );
 
             if child_board_value >= best_move_value {
@@ -362,143 +681,54 @@ impl Board {
     /// In other words, choose moves with the assumption that your opponent will make the
     /// best possible replies to your moves. Moves that are seemingly good, but are easily countered,
     /// are categorically eliminated by this algorithm.
+    ///
+    /// A search to a fixed depth allocates a transposition table of
+    /// `SMALL_TABLE_SIZE` bytes for each call; use `minimax_with_table` to
+    /// share one between calls. Searches of random moves can't use a table,
+    /// so they allocate none.
     pub fn minimax(
         &self,
         depth: Either<u8, (&[u8], ChaCha20Rng)>,
-        mut alpha: f64,
-        mut beta: f64,
+        alpha: i32,
+        beta: i32,
         is_maximizing: bool,
         getting_move_for: Color,
         board_count: &mut u64,
-    ) -> f64 {
-        *board_count += 1;
-
-        let (mut next_depth, max_moves) = match depth {
-            Either::Left(0) => {
-                return self.value_for(getting_move_for);
-            }
-            Either::Right((depth, _)) if depth.is_empty() => {
-                return self.value_for(getting_move_for);
-            }
-            Either::Left(depth) => (Either::Left(depth - 1), None),
-            Either::Right((depth, rng)) => (Either::Right((&depth[1..], rng)), Some(depth[0])),
+    ) -> i32 {
+        let mut table = match depth {
+            Either::Left(_) => TranspositionTable::new(SMALL_TABLE_SIZE),
+            Either::Right(_) => TranspositionTable::from_buffer(&mut []),
         };
-
-        let mut best_move_value;
-
-        if is_maximizing {
-            best_move_value = -999999.0;
-
-            if let Some(max_moves) = max_moves {
-                let Either::Right((_, rng)) = &mut next_depth else {
-                    panic!();
-                };
-                for m in self
-                    .get_legal_moves()
-                    .choose_multiple(rng, max_moves as usize)
-                {
-                    let child_board_value = self.apply_eval_move(m).minimax(
-                        next_depth.clone(),
-                        alpha,
-                        beta,
-                        !is_maximizing,
-                        getting_move_for,
-                        board_count,
-                    );
-
-                    if child_board_value > best_move_value {
-                        best_move_value = child_board_value;
-                    }
-
-                    if best_move_value > alpha {
-                        alpha = best_move_value
-                    }
-
-                    if beta <= alpha {
-                        return best_move_value;
-                    }
-                }
-            } else {
-                for m in self.get_legal_moves() {
-                    let child_board_value = self.apply_eval_move(m).minimax(
-                        next_depth.clone(),
-                        alpha,
-                        beta,
-                        !is_maximizing,
-                        getting_move_for,
-                        board_count,
-                    );
-
-                    if child_board_value > best_move_value {
-                        best_move_value = child_board_value;
-                    }
-
-                    if best_move_value > alpha {
-                        alpha = best_move_value
-                    }
-
-                    if beta <= alpha {
-                        return best_move_value;
-                    }
-                }
-            };
-        } else {
-            best_move_value = 999999.0;
-
-            if let Some(max_moves) = max_moves {
-                let Either::Right((_, rng)) = &mut next_depth else {
-                    panic!();
-                };
-                for m in self
-                    .get_legal_moves()
-                    .choose_multiple(rng, max_moves as usize)
-                {
-                    let child_board_value = self.apply_eval_move(m).minimax(
-                        next_depth.clone(),
-                        alpha,
-                        beta,
-                        !is_maximizing,
-                        getting_move_for,
-                        board_count,
-                    );
-                    if child_board_value < best_move_value {
-                        best_move_value = child_board_value;
-                    }
-
-                    if best_move_value < beta {
-                        beta = best_move_value
-                    }
-
-                    if beta <= alpha {
-                        return best_move_value;
-                    }
-                }
-            } else {
-                for m in self.get_legal_moves() {
-                    let child_board_value = self.apply_eval_move(m).minimax(
-                        next_depth.clone(),
-                        alpha,
-                        beta,
-                        !is_maximizing,
-                        getting_move_for,
-                        board_count,
-                    );
-                    if child_board_value < best_move_value {
-                        best_move_value = child_board_value;
-                    }
-
-                    if best_move_value < beta {
-                        beta = best_move_value
-                    }
-
-                    if beta <= alpha {
-                        return best_move_value;
-                    }
-                }
-            }
-        }
-
-        best_move_value
+        self.minimax_with_table(
+            depth,
+            alpha,
+            beta,
+            is_maximizing,
+            getting_move_for,
+            board_count,
+            &mut table,
+        )
+    }
+
+    /// Like `minimax`, but remembering searched positions in `table`, so
+    /// the searches after each move from a board can share them.
+    #[allow(clippy::too_many_arguments)]
+    pub fn minimax_with_table(
+        &self,
+        depth: Either<u8, (&[u8], ChaCha20Rng)>,
+        alpha: i32,
+        beta: i32,
+        is_maximizing: bool,
+        getting_move_for: Color,
+        board_count: &mut u64,
+        table: &mut TranspositionTable,
+    ) -> i32 {
+        let owned = core::mem::replace(table, TranspositionTable::from_buffer(&mut []));
+        let mut searcher = Searcher::new(owned);
+        let value = searcher.minimax(self, depth, alpha, beta, is_maximizing, getting_move_for);
+        *board_count += searcher.get_board_count();
+        *table = searcher.into_table();
+        value
     }
 }
 
@@ -600,29 +830,42 @@ impl Board {
             black_castling_rights: CastlingRights::default(),
 
             turn: WHITE,
+
+            halfmove_clock: 0,
+            fullmove_number: 1,
+
+            hash: 0,
         }
+        .with_zobrist_hash()
     }
 
     pub fn rating_bar(&self, len: usize) -> String {
         let (best_m, _, your_best_val) = self.get_best_next_move(2);
         let (_, _, your_lowest_val) = self.get_worst_next_move(2);
-        let mut your_val = your_best_val + your_lowest_val;
+        // widened, since the values of mates can add up past an i32
+        let mut your_val = your_best_val as i64 + your_lowest_val as i64;
         let (_, _, their_best_val) = self.apply_move(best_m).change_turn().get_best_next_move(2);
         let (_, _, their_lowest_val) = self.apply_move(best_m).change_turn().get_worst_next_move(2);
-        let mut their_val = their_best_val + their_lowest_val;
+        let mut their_val = their_best_val as i64 + their_lowest_val as i64;
 
-        if your_val < 0.0 {
+        if your_val < 0 {
             your_val = -your_val;
-            their_val += your_val * 2.0;
+            their_val += your_val * 2;
         }
 
-        if their_val < 0.0 {
+        if their_val < 0 {
             their_val = -their_val;
-            your_val += their_val * 2.0;
+            your_val += their_val * 2;
         }
 
-        let your_percentage = your_val / (your_val + their_val);
-        let their_percentage = their_val / (your_val + their_val);
+        let total = your_val + their_val;
+        let (your_len, their_len) = match total {
+            0 => (0, 0),
+            _ => (
+                (your_val * len as i64 / total) as usize,
+                (their_val * len as i64 / total) as usize,
+            ),
+        };
 
         let (your_color, their_color) = match self.turn {
             WHITE => ("▓", "░"),
@@ -630,13 +873,13 @@ impl Board {
         };
 
         let white = match self.turn {
-            WHITE => your_color.repeat((your_percentage * len as f64) as usize),
-            BLACK => their_color.repeat((their_percentage * len as f64) as usize),
+            WHITE => your_color.repeat(your_len),
+            BLACK => their_color.repeat(their_len),
         };
 
         let black = match self.turn {
-            BLACK => your_color.repeat((your_percentage * len as f64) as usize),
-            WHITE => their_color.repeat((their_percentage * len as f64) as usize),
+            BLACK => your_color.repeat(your_len),
+            WHITE => their_color.repeat(their_len),
         };
 
         white + &black
@@ -653,13 +896,94 @@ impl Board {
         self.en_passant
     }
 
+    /// Get the number of halfmoves since the last capture or pawn move
+    #[inline]
+    pub fn get_halfmove_clock(&self) -> u16 {
+        self.halfmove_clock
+    }
+
+    /// Get the number of the current full move, starting at 1 and
+    /// incremented after each move by black
+    #[inline]
+    pub fn get_fullmove_number(&self) -> u16 {
+        self.fullmove_number
+    }
+
+    /// Do two boards hold the same position?
+    ///
+    /// Like `==`, this ignores the halfmove clock and fullmove number, so it
+    /// can be used to detect repeated positions. Unlike `==`, an en passant
+    /// square only counts when the player to move can capture onto it, as
+    /// in the FIDE rules for repetition.
+    pub fn is_same_position(&self, other: &Self) -> bool {
+        self.hash == other.hash
+            && self.squares == other.squares
+            && self.capturable_en_passant() == other.capturable_en_passant()
+            && self.white_castling_rights == other.white_castling_rights
+            && self.black_castling_rights == other.black_castling_rights
+            && self.turn == other.turn
+    }
+
+    /// Get the Zobrist hash of the position.
+    ///
+    /// The hash covers the pieces, the side to move, castling rights and the
+    /// en passant file, but not the move counters, so repeated positions
+    /// share a hash. As in Polyglot, the en passant file only counts when
+    /// the player to move can capture en passant. It is kept up to date as moves are applied, and is the
+    /// same across builds and platforms, so it can be stored.
+    #[inline]
+    pub fn zobrist_hash(&self) -> u64 {
+        self.hash ^ zobrist::en_passant_key(self.capturable_en_passant())
+    }
+
+    // the hash of everything but the en passant square, which is kept up
+    // to date as moves are applied. whether an en passant capture is
+    // possible depends on the whole position, so it's checked when the
+    // hash is asked for instead.
+    fn compute_zobrist_hash(&self) -> u64 {
+        let mut hash =
+            zobrist::castling_key(self.white_castling_rights, self.black_castling_rights);
+        if self.turn == BLACK {
+            hash ^= zobrist::side_key();
+        }
+        for square in &self.squares {
+            if let Some(piece) = square.get_piece() {
+                hash ^= zobrist::piece_key(piece);
+            }
+        }
+        hash
+    }
+
+    fn with_zobrist_hash(mut self) -> Self {
+        self.hash = self.compute_zobrist_hash();
+        self
+    }
+
+    // the en passant square, if the player to move has a legal capture
+    // onto it.
+    fn capturable_en_passant(&self) -> Option<Position> {
+        let en_passant = self.en_passant?;
+        let behind = en_passant.pawn_back(self.turn);
+        let can_capture = [behind.next_left(), behind.next_right()]
+            .iter()
+            .any(|&from| {
+                matches!(self.get_piece(from), Some(Piece::Pawn(color, _)) if color == self.turn)
+                    && self.is_legal_move(Move::Piece(from, en_passant), self.turn)
+            });
+        if can_capture {
+            Some(en_passant)
+        } else {
+            None
+        }
+    }
+
     /// Remove all of the pieces for a given player
     pub fn remove_all(&self, color: Color) -> Self {
         let mut result = *self;
-        for square in &mut result.squares {
+        for square in self.squares {
             if let Some(piece) = square.get_piece() {
                 if piece.get_color() == color {
-                    *square = EMPTY_SQUARE
+                    result.set_square(piece.get_pos(), EMPTY_SQUARE);
                 }
             }
         }
@@ -670,10 +994,13 @@ impl Board {
     /// Convert all of a given players pieces to queens
     pub fn queen_all(&self, color: Color) -> Self {
         let mut result = *self;
-        for square in &mut result.squares {
+        for square in self.squares {
             if let Some(piece) = square.get_piece() {
                 if !piece.is_king() && piece.get_color() == color {
-                    *square = Square::from(Piece::Queen(color, piece.get_pos()))
+                    result.set_square(
+                        piece.get_pos(),
+                        Square::from(Piece::Queen(color, piece.get_pos())),
+                    );
                 }
             }
         }
@@ -684,9 +1011,11 @@ impl Board {
     /// Make the game a certain player's turn
     #[inline]
     pub fn set_turn(&self, color: Color) -> Self {
-        let mut result = *self;
-        result.turn = color;
-        result
+        if self.turn == color {
+            *self
+        } else {
+            self.change_turn()
+        }
     }
 
     /// Get the value of the material advantage of a certain player
@@ -708,14 +1037,25 @@ impl Board {
     }
 
     #[inline]
-    fn get_square(&mut self, pos: Position) -> &mut Square {
-        &mut self.squares[((7 - pos.get_row()) * 8 + pos.get_col()) as usize]
+    fn set_square(&mut self, pos: Position, square: Square) {
+        let index = ((7 - pos.get_row()) * 8 + pos.get_col()) as usize;
+        if let Some(piece) = self.squares[index].get_piece() {
+            self.hash ^= zobrist::piece_key(piece);
+        }
+        if let Some(piece) = square.get_piece() {
+            self.hash ^= zobrist::piece_key(piece);
+        }
+        self.squares[index] = square;
+    }
+
+    #[inline]
+    fn set_en_passant(&mut self, en_passant: Option<Position>) {
+        self.en_passant = en_passant;
     }
 
     #[inline]
     fn add_piece(&mut self, piece: Piece) {
-        let pos = piece.get_pos();
-        *self.get_square(pos) = Square::from(piece);
+        self.set_square(piece.get_pos(), Square::from(piece));
     }
 
     /// Does a square have any piece?
@@ -737,6 +1077,23 @@ impl Board {
         }
     }
 
+    /// Get the piece that move `m` would capture, including a pawn taken
+    /// en passant.
+    pub fn get_captured_piece(&self, m: Move) -> Option<Piece> {
+        let (from, to) = match m {
+            Move::Piece(from, to) | Move::Promotion(from, to, _) => (from, to),
+            _ => return None,
+        };
+        let piece = self.get_piece(from)?;
+        if self.has_enemy_piece(to, piece.get_color()) {
+            self.get_piece(to)
+        } else if piece.is_pawn() && from.get_col() != to.get_col() {
+            self.get_piece(Position::new(from.get_row(), to.get_col()))
+        } else {
+            None
+        }
+    }
+
     /// If a square at a given position has an enemy piece from a given
     /// ally color, return true. Otherwise, return false.
     ///
@@ -818,15 +1175,14 @@ impl Board {
 
     fn move_piece(&self, from: Position, to: Position, promotion: Option<Piece>) -> Self {
         let mut result = *self;
-        result.en_passant = None;
+        result.set_en_passant(None);
 
         if from.is_off_board() || to.is_off_board() {
             return result;
         }
 
-        let from_square = result.get_square(from);
-        if let Some(mut piece) = from_square.get_piece() {
-            *from_square = EMPTY_SQUARE;
+        if let Some(mut piece) = result.get_piece(from) {
+            result.set_square(from, EMPTY_SQUARE);
 
             if piece.is_pawn() && (to.get_row() == 0 || to.get_row() == 7) {
                 piece = match promotion {
@@ -847,23 +1203,35 @@ impl Board {
             }
 
             if piece.is_starting_pawn() && (from.get_row() - to.get_row()).abs() == 2 {
-                result.en_passant = Some(to.pawn_back(piece.get_color()))
+                result.set_en_passant(Some(to.pawn_back(piece.get_color())))
             }
 
             result.add_piece(piece.move_to(to));
 
-            let castling_rights = match piece.get_color() {
-                WHITE => &mut result.white_castling_rights,
-                BLACK => &mut result.black_castling_rights,
-            };
-
+            result.hash ^=
+                zobrist::castling_key(result.white_castling_rights, result.black_castling_rights);
             if piece.is_king() {
-                castling_rights.disable_all();
-            } else if piece.is_queenside_rook() {
-                castling_rights.disable_queenside();
-            } else if piece.is_kingside_rook() {
-                castling_rights.disable_kingside();
+                match piece.get_color() {
+                    WHITE => result.white_castling_rights.disable_all(),
+                    BLACK => result.black_castling_rights.disable_all(),
+                }
+            }
+
+            // a rook that leaves its starting square, or is captured on it,
+            // can no longer castle
+            for (row, castling_rights) in [
+                (0, &mut result.white_castling_rights),
+                (7, &mut result.black_castling_rights),
+            ] {
+                if from == Position::new(row, 0) || to == Position::new(row, 0) {
+                    castling_rights.disable_queenside();
+                }
+                if from == Position::new(row, 7) || to == Position::new(row, 7) {
+                    castling_rights.disable_kingside();
+                }
             }
+            result.hash ^=
+                zobrist::castling_key(result.white_castling_rights, result.black_castling_rights);
         }
 
         result
@@ -908,6 +1276,7 @@ impl Board {
                     && self.white_castling_rights.can_queenside_castle()
                     && !self.is_in_check(color)
                     && !self.is_threatened(Position::queen_pos(color), color)
+                    && !self.is_threatened(Position::queen_pos(color).next_left(), color)
             }
             BLACK => {
                 self.has_no_piece(Position::new(7, 1))
@@ -918,6 +1287,7 @@ impl Board {
                     && self.black_castling_rights.can_queenside_castle()
                     && !self.is_in_check(color)
                     && !self.is_threatened(Position::queen_pos(color), color)
+                    && !self.is_threatened(Position::queen_pos(color).next_left(), color)
             }
         }
     }
@@ -938,8 +1308,8 @@ impl Board {
                     let piece = Piece::Pawn(c, pos);
                     ((if let Some(en_passant) = self.en_passant {
                         (en_passant == from.pawn_up(player_color).next_left()
-                            || en_passant == from.pawn_up(player_color).next_right()
-                                && en_passant == to)
+                            || en_passant == from.pawn_up(player_color).next_right())
+                            && en_passant == to
                             && c == player_color
                     } else {
                         false
@@ -1017,6 +1387,24 @@ impl Board {
                 && self.has_insufficient_material(!self.turn))
     }
 
+    /// Can the current player claim a draw by the fifty-move rule?
+    ///
+    /// This is possible once fifty moves have been played by each player
+    /// without any capture or pawn move.
+    #[inline]
+    pub fn can_claim_fifty_move_draw(&self) -> bool {
+        self.halfmove_clock >= 100
+    }
+
+    /// Is the game drawn by the seventy-five-move rule?
+    ///
+    /// Unlike the fifty-move rule, this draw is automatic once seventy-five
+    /// moves have been played by each player without any capture or pawn move.
+    #[inline]
+    pub fn is_seventy_five_move_draw(&self) -> bool {
+        self.halfmove_clock >= 150
+    }
+
     /// Is the current player in checkmate?
     pub fn is_checkmate(&self) -> bool {
         self.is_in_check(self.get_current_player_color()) && self.get_legal_moves().next().is_none()
@@ -1026,11 +1414,12 @@ impl Board {
     #[inline]
     pub fn change_turn(mut self) -> Self {
         self.turn = !self.turn;
+        self.hash ^= zobrist::side_key();
         self
     }
 
     fn apply_move(&self, m: Move) -> Self {
-        match m {
+        let mut result = match m {
             Move::KingSideCastle => {
                 if let Some(king_pos) = self.get_king_pos(self.turn) {
                     let rook_pos = match self.turn {
@@ -1066,17 +1455,117 @@ impl Board {
                         || en_passant == from.pawn_up(player_color).next_right())
                         && en_passant == to
                     {
-                        result.squares[((7 - en_passant.pawn_back(player_color).get_row()) * 8
-                            + en_passant.get_col())
-                            as usize] = EMPTY_SQUARE;
+                        result.set_square(en_passant.pawn_back(player_color), EMPTY_SQUARE);
                     }
                 }
 
                 result
             }
             Move::Promotion(from, to, promotion) => self.move_piece(from, to, Some(promotion)),
-            Move::Resign => self.remove_all(self.turn).queen_all(!self.turn),
+            Move::Resign => return self.remove_all(self.turn).queen_all(!self.turn),
+        };
+
+        result.halfmove_clock = match m {
+            Move::Piece(from, to) | Move::Promotion(from, to, _)
+                if self.has_piece(to) || self.get_piece(from).map_or(false, |p| p.is_pawn()) =>
+            {
+                0
+            }
+            _ => self.halfmove_clock.saturating_add(1),
+        };
+        if self.turn == BLACK {
+            result.fullmove_number = self.fullmove_number.saturating_add(1);
         }
+
+        result
+    }
+
+    /// Write a move in Standard Algebraic Notation (SAN), such as `"Nbd7"`,
+    /// `"exd6"`, `"e8=Q+"` or `"O-O#"`.
+    ///
+    /// The move is assumed to be legal for the current player. When another
+    /// piece of the same type could also reach the destination square, the
+    /// moving piece is disambiguated by its file, its rank, or both, using
+    /// the shortest form that identifies it. A pawn reaching the last rank
+    /// with `Move::Piece` is written as a promotion to a queen.
+    ///
+    /// `Move::Resign` has no algebraic notation and is written as `"Resign"`.
+    pub fn to_san(&self, m: Move) -> String {
+        let mut san = match m {
+            Move::KingSideCastle => String::from("O-O"),
+            Move::QueenSideCastle => String::from("O-O-O"),
+            Move::Resign => return m.to_string(),
+            Move::Piece(from, to) | Move::Promotion(from, to, _) => {
+                let piece = match self.get_piece(from) {
+                    Some(piece) => piece,
+                    None => return m.to_string(),
+                };
+                let is_capture = self.get_captured_piece(m).is_some();
+
+                let mut san = String::from(piece.get_san_letter());
+                if piece.is_pawn() {
+                    if is_capture {
+                        san.push_str(&from.to_string()[..1]);
+                    }
+                } else {
+                    // other pieces of the same type that can reach `to`
+                    let mut rivals = self
+                        .get_legal_moves()
+                        .filter_map(|other| match other {
+                            Move::Piece(other_from, other_to)
+                            | Move::Promotion(other_from, other_to, _)
+                                if other_to == to && other_from != from =>
+                            {
+                                self.get_piece(other_from)
+                            }
+                            _ => None,
+                        })
+                        .filter(|other| other.get_name() == piece.get_name())
+                        .map(|other| other.get_pos())
+                        .collect::<Vec<Position>>();
+                    rivals.dedup();
+
+                    if !rivals.is_empty() {
+                        let from_name = from.to_string();
+                        if rivals.iter().all(|pos| pos.get_col() != from.get_col()) {
+                            san.push_str(&from_name[..1]);
+                        } else if rivals.iter().all(|pos| pos.get_row() != from.get_row()) {
+                            san.push_str(&from_name[1..]);
+                        } else {
+                            san.push_str(&from_name);
+                        }
+                    }
+                }
+
+                if is_capture {
+                    san.push('x');
+                }
+                san.push_str(&to.to_string());
+
+                if piece.is_pawn() && (to.get_row() == 0 || to.get_row() == 7) {
+                    san.push('=');
+                    san.push_str(match m {
+                        Move::Promotion(_, _, promotion)
+                            if !(promotion.is_king() || promotion.is_pawn()) =>
+                        {
+                            promotion.get_san_letter()
+                        }
+                        // queen by default, see `move_piece`
+                        _ => "Q",
+                    });
+                }
+                san
+            }
+        };
+
+        let next_turn = self.apply_eval_move(m);
+        if next_turn.is_checkmate() {
+            san.push('#');
+        } else if next_turn.is_in_check(next_turn.get_turn_color()) {
+            san.push('+');
+        }
+
+        san
     }
 
     /// Play a move and confirm it is legal.
@@ -1089,7 +1578,7 @@ impl Board {
             let next_turn = self.apply_move(m).change_turn();
             if next_turn.is_checkmate() {
                 GameResult::Victory(current_color)
-            } else if next_turn.is_stalemate() {
+            } else if next_turn.is_stalemate() || next_turn.is_seventy_five_move_draw() {
                 GameResult::Stalemate
             } else {
                 GameResult::Continuing(next_turn)
@@ -1099,3 +1588,221 @@ impl Board {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_perft() {
+        let positions = [
+            (Board::default(), vec![1, 20, 400, 8902]),
+            // kiwipete
+            (
+                parse_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1")
+                    .unwrap(),
+                vec![1, 48, 2039, 97862],
+            ),
+            (
+                parse_fen("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1").unwrap(),
+                vec![1, 14, 191, 2812, 43238],
+            ),
+            (
+                parse_fen("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1")
+                    .unwrap(),
+                vec![1, 6, 264, 9467],
+            ),
+            (
+                parse_fen("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8").unwrap(),
+                vec![1, 44, 1486, 62379],
+            ),
+            (
+                parse_fen(
+                    "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+                )
+                .unwrap(),
+                vec![1, 46, 2079, 89890],
+            ),
+        ];
+
+        for (board, counts) in positions {
+            for (depth, count) in counts.into_iter().enumerate() {
+                assert_eq!(
+                    board.perft(depth as u8),
+                    count,
+                    "{} at depth {}",
+                    board,
+                    depth
+                );
+            }
+        }
+
+        let divide = Board::default().perft_divide(2);
+        assert_eq!(divide.len(), 20);
+        assert!(divide.contains(&(Move::Piece(E2, E4), 20)));
+    }
+
+    #[test]
+    fn test_zobrist_hash() {
+        // every reachable position keeps its incremental hash in sync
+        fn walk(board: &Board, depth: u8) {
+            assert_eq!(board.hash, board.compute_zobrist_hash(), "{}", board);
+            if depth > 0 {
+                for m in board.get_legal_moves() {
+                    walk(&board.apply_eval_move(m), depth - 1);
+                }
+            }
+        }
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        ] {
+            walk(&parse_fen(fen).unwrap(), 2);
+        }
+
+        // transpositions share a hash, and hashes do not change between builds
+        let start = Board::default();
+        let shuffled = [
+            Move::Piece(G1, F3),
+            Move::Piece(G8, F6),
+            Move::Piece(F3, G1),
+            Move::Piece(F6, G8),
+        ]
+        .iter()
+        .fold(start, |board, m| board.apply_eval_move(*m));
+        assert_eq!(shuffled.zobrist_hash(), start.zobrist_hash());
+        assert_ne!(start.change_turn().zobrist_hash(), start.zobrist_hash());
+        assert_eq!(start.zobrist_hash(), 0xf67e_4f21_df2e_24e7);
+    }
+
+    #[test]
+    fn test_is_same_position() {
+        let same = |with: &str, without: &str| {
+            let with = parse_fen(with).unwrap();
+            let without = parse_fen(without).unwrap();
+            assert_ne!(with, without);
+            assert_eq!(
+                with.is_same_position(&without),
+                with.zobrist_hash() == without.zobrist_hash()
+            );
+            with.is_same_position(&without)
+        };
+        // no black pawn can take on e3
+        assert!(same(
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1"
+        ));
+        assert!(!same(
+            "rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+            "rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1"
+        ));
+        // taking on e3 would leave the black king in check from the rook
+        assert!(same(
+            "8/8/8/8/R2pP2k/8/8/4K3 b - e3 0 1",
+            "8/8/8/8/R2pP2k/8/8/4K3 b - - 0 1"
+        ));
+    }
+
+    #[test]
+    fn test_borsh() {
+        let board =
+            parse_fen("rnbqkbnr/pppp1ppp/8/8/3Pp3/8/PPP1PPPP/RNBQKBNR b Kq d3 5 12").unwrap();
+
+        // the layout is the one boards were stored in before the counters
+        let bytes = board.try_to_vec().unwrap();
+        let legacy = (
+            board.squares,
+            board.en_passant,
+            board.white_castling_rights,
+            board.black_castling_rights,
+            board.turn,
+        );
+        assert_eq!(bytes, legacy.try_to_vec().unwrap());
+
+        let read = Board::try_from_slice(&bytes).unwrap();
+        assert_eq!(read, board);
+        assert_eq!(read.zobrist_hash(), board.zobrist_hash());
+        assert_eq!(read.get_halfmove_clock(), 0);
+        assert_eq!(read.get_fullmove_number(), 1);
+    }
+
+    #[test]
+    fn test_to_san() {
+        let board = Board::default();
+        assert_eq!(board.to_san(Move::Piece(E2, E4)), "e4");
+        assert_eq!(board.to_san(Move::Piece(G1, F3)), "Nf3");
+
+        // file disambiguation
+        let board = parse_fen("rn2k3/8/5n2/8/8/8/8/4K3 b - - 0 1").unwrap();
+        assert_eq!(board.to_san(Move::Piece(B8, D7)), "Nbd7");
+        assert_eq!(board.to_san(Move::Piece(F6, D7)), "Nfd7");
+        assert_eq!(board.to_san(Move::Piece(F6, H5)), "Nh5");
+
+        // rank disambiguation
+        let board = parse_fen("4k3/8/8/R7/8/8/8/R3K3 w - - 0 1").unwrap();
+        assert_eq!(board.to_san(Move::Piece(A1, A3)), "R1a3");
+        assert_eq!(board.to_san(Move::Piece(A5, A3)), "R5a3");
+
+        // file and rank disambiguation
+        let board = parse_fen("4k3/8/8/8/8/Q1Q5/8/Q1Q1K3 w - - 0 1").unwrap();
+        assert_eq!(board.to_san(Move::Piece(A1, B2)), "Qa1b2");
+
+        // captures and en passant
+        let board = parse_fen("4k3/8/8/3pP3/8/5n2/8/4KN2 w - d6 0 1").unwrap();
+        assert_eq!(board.to_san(Move::Piece(E5, D6)), "exd6");
+        assert_eq!(board.to_san(Move::Piece(E1, F2)), "Kf2");
+        assert_eq!(board.to_san(Move::Piece(E1, E2)), "Ke2");
+
+        // promotions with check
+        let board = parse_fen("k2r4/4P3/8/8/8/8/8/7K w - - 0 1").unwrap();
+        assert_eq!(board.to_san(Move::Piece(E7, E8)), "e8=Q");
+        assert_eq!(
+            board.to_san(Move::Promotion(E7, D8, Piece::Knight(WHITE, D8))),
+            "exd8=N"
+        );
+        let board = parse_fen("k7/4P3/8/8/8/8/8/7K w - - 0 1").unwrap();
+        assert_eq!(
+            board.to_san(Move::Promotion(E7, E8, Piece::Queen(WHITE, E8))),
+            "e8=Q+"
+        );
+
+        // castling into checkmate
+        let board = parse_fen("4rkr1/4p1p1/8/8/8/8/8/4K2R w K - 0 1").unwrap();
+        assert_eq!(board.to_san(Move::KingSideCastle), "O-O#");
+    }
+    #[test]
+    fn test_value_for() {
+        // the start position is symmetric
+        let board = Board::default();
+        assert_eq!(board.value_for(WHITE), 0);
+        assert_eq!(board.value_for(BLACK), 0);
+
+        // an extra pawn is worth about a pawn, give or take its square
+        let board = parse_fen("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1").unwrap();
+        let value = board.value_for(WHITE);
+        assert!((PAWN_VALUE - 50..=PAWN_VALUE + 50).contains(&value));
+        assert_eq!(board.value_for(BLACK), -value);
+        assert_eq!(centipawns_to_pawns(150), 1.5);
+    }
+
+    #[test]
+    fn test_game_phase() {
+        assert_eq!(Board::default().get_game_phase(), MAX_PHASE);
+        let endgame = parse_fen("4k3/8/8/8/8/8/4P3/6K1 w - - 0 1").unwrap();
+        assert_eq!(endgame.get_game_phase(), 0);
+
+        // the king belongs in the center once the pieces are gone
+        let centralized = parse_fen("4k3/8/8/8/4K3/8/4P3/8 w - - 0 1").unwrap();
+        assert!(centralized.value_for(WHITE) > endgame.value_for(WHITE));
+
+        // but behind its pawns while they're still on the board
+        let board = parse_fen("rnbqkbnr/pppppppp/8/8/4K3/8/PPPPPPPP/RNBQ1BNR w kq - 0 1").unwrap();
+        assert_eq!(board.get_game_phase(), MAX_PHASE);
+        assert!(board.value_for(WHITE) < Board::default().value_for(WHITE));
+
+        // and passed pawns are worth more the further they've run
+        let advanced = parse_fen("4k3/8/4P3/8/8/8/8/6K1 w - - 0 1").unwrap();
+        assert!(advanced.value_for(WHITE) > endgame.value_for(WHITE) + 30);
+    }
+}
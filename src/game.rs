@@ -1,16 +1,26 @@
 use alloc::string::String;
+use alloc::vec::Vec;
 
 use crate::board::Board;
-use crate::util::{format_fen, parse_fen, parse_san_move};
-use crate::{Color, GameResult};
+use crate::pgn;
+use crate::util::{format_fen, parse_fen, parse_san_move, SanError};
+use crate::{Color, GameResult, Move};
 
 pub enum GameAction {
     // accept draw if previous action was OfferDraw
     AcceptDraw,
+    // claim a draw by threefold repetition or the fifty-move rule
+    ClaimDraw,
     // make move, using san notation
     MakeMove(String),
     // make move and offer draw, using san notiation
     OfferDraw(String),
+    // ask to take back the last move, by the player of the given color,
+    // who must have made it
+    RequestTakeback(Color),
+    // take back the last move if the other player asked to, by the player
+    // of the given color
+    AcceptTakeback(Color),
     // resign
     Resign,
 }
@@ -42,6 +52,41 @@ pub enum GameOver {
     BlackResigns,
     Stalemate,
     DrawAccepted,
+    // claimed once the same position occurred three times
+    ThreefoldRepetition,
+    // automatic once the same position occurred five times
+    FivefoldRepetition,
+    // claimed after fifty moves each without capture or pawn move
+    FiftyMoveRule,
+    // automatic after seventy-five moves each without capture or pawn move
+    SeventyFiveMoveRule,
+    // the result of a pgn game that ended without checkmate or stalemate,
+    // where the reason, e.g. resignation or agreement, isn't recorded
+    WhiteWins,
+    BlackWins,
+    Drawn,
+}
+
+// a move played in a game, with the position it was played from
+#[derive(Clone, Debug, PartialEq)]
+pub struct HistoryEntry {
+    pub board: Board,
+    pub chess_move: Move,
+    // pgn commentary following the move
+    pub comment: Option<String>,
+    // pgn numeric annotation glyphs, e.g. 1 for "!" and 2 for "?"
+    pub nags: Vec<u8>,
+}
+
+impl HistoryEntry {
+    pub fn new(board: Board, chess_move: Move) -> Self {
+        HistoryEntry {
+            board,
+            chess_move,
+            comment: None,
+            nags: Vec::new(),
+        }
+    }
 }
 
 // wrapper around chess_engine::Board
@@ -52,7 +97,18 @@ pub enum GameOver {
 pub struct Game {
     pub board: Board,
     pub draw_offered: Option<Color>,
+    pub takeback_requested: Option<Color>,
     pub status: Option<GameOver>,
+    // pgn tag pairs, in the order they were read or set
+    pub tags: Vec<(String, String)>,
+    // pgn comment before the first move, about the whole game
+    pub comment: Option<String>,
+    // pgn NAGs before the first move, about the whole game
+    pub nags: Vec<u8>,
+    // every move played so far, oldest first
+    pub(crate) history: Vec<HistoryEntry>,
+    // moves taken back with undo, most recent last
+    pub(crate) undone: Vec<HistoryEntry>,
 }
 
 impl Game {
@@ -70,12 +126,18 @@ impl Game {
         Ok(Game {
             board,
             draw_offered,
+            takeback_requested: None,
             status,
+            tags: Vec::new(),
+            comment: None,
+            nags: Vec::new(),
+            history: Vec::new(),
+            undone: Vec::new(),
         })
     }
 
-    pub fn to_fen(&self, halfmove_clock: u8, fullmove_number: u8) -> Result<String, String> {
-        format_fen(&self.board, halfmove_clock, fullmove_number)
+    pub fn to_fen(&self) -> Result<String, String> {
+        format_fen(&self.board)
     }
 
     // convenience accessor for board.get_turn_color
@@ -83,6 +145,71 @@ impl Game {
         self.board.get_turn_color()
     }
 
+    // number of times the current position has occurred, including now
+    pub fn repetition_count(&self) -> usize {
+        1 + self
+            .history
+            .iter()
+            .filter(|entry| entry.board.is_same_position(&self.board))
+            .count()
+    }
+
+    // whether the current turn may claim a draw by threefold repetition
+    // or the fifty-move rule
+    pub fn can_claim_draw(&self) -> bool {
+        self.repetition_count() >= 3 || self.board.can_claim_fifty_move_draw()
+    }
+
+    // best move for current turn, scoring repetitions of earlier positions as draws
+    pub fn get_best_next_move(&self, depth: u8) -> (Move, u64, i32) {
+        let history: Vec<Board> = self.history.iter().map(|entry| entry.board).collect();
+        self.board.get_best_next_move_with_history(depth, &history)
+    }
+
+    // every move played so far, oldest first
+    pub fn history(&self) -> impl DoubleEndedIterator<Item = &HistoryEntry> + ExactSizeIterator {
+        self.history.iter()
+    }
+
+    // take back the last move, returning it
+    //
+    // the game continues from the position before the move, even if it
+    // was over, and pending draw offers and takeback requests are dropped.
+    // the move can be played again with redo until another move is made.
+    pub fn undo(&mut self) -> Option<Move> {
+        let entry = self.history.pop()?;
+        let chess_move = entry.chess_move;
+        self.board = entry.board;
+        self.status = None;
+        self.draw_offered = None;
+        self.takeback_requested = None;
+        self.undone.push(entry);
+        Some(chess_move)
+    }
+
+    // play the last move taken back with undo again, returning it
+    pub fn redo(&mut self) -> Option<Move> {
+        let entry = self.undone.pop()?;
+        let undone = core::mem::take(&mut self.undone);
+        self.play_move(entry.chess_move).ok()?;
+        if let Some(played) = self.history.last_mut() {
+            played.comment = entry.comment;
+            played.nags = entry.nags;
+        }
+        self.undone = undone;
+        Some(entry.chess_move)
+    }
+
+    // value of a pgn tag such as "Event" or "White"
+    pub fn get_tag(&self, name: &str) -> Option<&str> {
+        pgn::get_tag(&self.tags, name)
+    }
+
+    // add a pgn tag, or replace the value of an existing one
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        pgn::set_tag(&mut self.tags, name, value)
+    }
+
     // make a move for current turn
     pub fn make_move(&mut self, action: &GameAction) -> Result<&Option<GameOver>, GameError> {
         if self.status.is_some() {
@@ -90,8 +217,11 @@ impl Game {
         }
         match action {
             GameAction::AcceptDraw => self.accept_draw(),
+            GameAction::ClaimDraw => self.claim_draw(),
             GameAction::MakeMove(move_str) => self.move_piece(move_str, false),
             GameAction::OfferDraw(move_str) => self.move_piece(move_str, true),
+            GameAction::RequestTakeback(color) => self.request_takeback(*color),
+            GameAction::AcceptTakeback(color) => self.accept_takeback(*color),
             GameAction::Resign => self.resign(),
         }
     }
@@ -107,6 +237,41 @@ impl Game {
         Err(GameError::InvalidMove {})
     }
 
+    // ask the player to move to allow taking back the previous move,
+    // which `color` must have played
+    fn request_takeback(&mut self, color: Color) -> Result<&Option<GameOver>, GameError> {
+        if self.history.is_empty() || color == self.get_turn_color() {
+            return Err(GameError::InvalidMove {});
+        }
+        self.takeback_requested = Some(color);
+        Ok(&self.status)
+    }
+
+    // take back the previous move if the other player requested it
+    fn accept_takeback(&mut self, color: Color) -> Result<&Option<GameOver>, GameError> {
+        if let Some(requester) = self.takeback_requested {
+            if requester != color {
+                self.undo();
+                return Ok(&self.status);
+            }
+        }
+        Err(GameError::InvalidMove {})
+    }
+
+    // claim draw if the current position occurred three times, or
+    // fifty moves were played without capture or pawn move
+    fn claim_draw(&mut self) -> Result<&Option<GameOver>, GameError> {
+        if self.repetition_count() >= 3 {
+            self.status = Some(GameOver::ThreefoldRepetition);
+            return Ok(&self.status);
+        }
+        if self.board.can_claim_fifty_move_draw() {
+            self.status = Some(GameOver::FiftyMoveRule);
+            return Ok(&self.status);
+        }
+        Err(GameError::InvalidMove {})
+    }
+
     // move a piece and optionally offer a draw
     fn move_piece(
         &mut self,
@@ -115,20 +280,29 @@ impl Game {
     ) -> Result<&Option<GameOver>, GameError> {
         let chess_move = match parse_san_move(&self.board, movestr) {
             Ok(chess_move) => chess_move,
+            Err(SanError::Ambiguous) => {
+                return Err(GameError::AmbiguousMove {});
+            }
             Err(_) => {
                 return Err(GameError::InvalidMove {});
             }
         };
 
-        self.draw_offered = match draw_offered {
-            true => Some(self.get_turn_color()),
-            false => None,
-        };
-        self.status = match self.board.play_move(chess_move) {
-            GameResult::Continuing(board) => {
-                self.board = board;
-                None
-            }
+        self.play_move(chess_move)?;
+        if draw_offered && self.status.is_none() {
+            self.draw_offered = Some(!self.get_turn_color());
+        }
+        Ok(&self.status)
+    }
+
+    // play an already parsed move for current turn
+    //
+    // a move that ends the game is played like any other, so `board` holds
+    // the final position and the move is in the history. up to 0.1.2 the
+    // board was left at the position before a checkmate or stalemate.
+    pub(crate) fn play_move(&mut self, chess_move: Move) -> Result<&Option<GameOver>, GameError> {
+        let status = match self.board.play_move(chess_move) {
+            GameResult::Continuing(_) => None,
             GameResult::IllegalMove(_) => {
                 return Err(GameError::InvalidMove {});
             }
@@ -138,6 +312,19 @@ impl Game {
                 Color::White => Some(GameOver::WhiteCheckmates),
             },
         };
+
+        self.draw_offered = None;
+        self.takeback_requested = None;
+        self.undone.clear();
+        self.history.push(HistoryEntry::new(self.board, chess_move));
+        self.board = self.board.apply_eval_move(chess_move);
+        self.status = match status {
+            None if self.repetition_count() >= 5 => Some(GameOver::FivefoldRepetition),
+            Some(GameOver::Stalemate) if self.board.is_seventy_five_move_draw() => {
+                Some(GameOver::SeventyFiveMoveRule)
+            }
+            status => status,
+        };
         Ok(&self.status)
     }
 
@@ -155,7 +342,7 @@ impl Game {
 mod tests {
     use super::*;
     use crate::piece::Piece;
-    use crate::position::Position;
+    use crate::position::*;
 
     #[test]
     fn test_game_moves() {
@@ -177,6 +364,12 @@ mod tests {
                 .expect(game_move);
         }
         assert_eq!(game.status, Some(GameOver::BlackCheckmates));
+        // the mating move is played
+        assert_eq!(
+            game.to_fen().unwrap(),
+            "rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3"
+        );
+        assert_eq!(game.history().len(), 4);
     }
 
     #[test]
@@ -197,4 +390,144 @@ mod tests {
             Some(Piece::Rook(Color::White, Position::pgn("c8").unwrap()))
         )
     }
+
+    #[test]
+    fn test_repetition() {
+        let mut game = Game::default();
+        assert_eq!(
+            game.make_move(&GameAction::ClaimDraw),
+            Err(GameError::InvalidMove)
+        );
+
+        let shuffle = vec!["Nf3", "Nf6", "Ng1", "Ng8"];
+        for game_move in &shuffle {
+            game.make_move(&GameAction::from(*game_move))
+                .expect(game_move);
+        }
+        assert_eq!(game.repetition_count(), 2);
+        assert!(!game.can_claim_draw());
+
+        for game_move in &shuffle {
+            game.make_move(&GameAction::from(*game_move))
+                .expect(game_move);
+        }
+        assert_eq!(game.repetition_count(), 3);
+        assert!(game.can_claim_draw());
+
+        // playing on is allowed until the fifth occurrence
+        for game_move in shuffle.iter().chain(shuffle[..3].iter()) {
+            game.make_move(&GameAction::from(*game_move))
+                .expect(game_move);
+        }
+        assert_eq!(game.status, None);
+        game.make_move(&GameAction::from(shuffle[3])).unwrap();
+        assert_eq!(game.status, Some(GameOver::FivefoldRepetition));
+    }
+
+    #[test]
+    fn test_fifty_move_rule() {
+        let mut game = Game::from_fen("8/8/8/4k3/8/8/4K3/7R w - - 98 70", None, None).unwrap();
+        game.make_move(&GameAction::from("Rh2")).unwrap();
+        assert_eq!(
+            game.make_move(&GameAction::ClaimDraw),
+            Err(GameError::InvalidMove)
+        );
+        game.make_move(&GameAction::from("Kd5")).unwrap();
+        assert_eq!(game.to_fen().unwrap(), "8/8/8/3k4/8/8/4K2R/8 w - - 100 71");
+        assert_eq!(
+            game.make_move(&GameAction::ClaimDraw),
+            Ok(&Some(GameOver::FiftyMoveRule))
+        );
+
+        let mut game = Game::from_fen("8/8/8/4k3/8/8/4K3/7R w - - 149 95", None, None).unwrap();
+        assert_eq!(
+            game.make_move(&GameAction::from("Rh2")),
+            Ok(&Some(GameOver::SeventyFiveMoveRule))
+        );
+    }
+
+    #[test]
+    fn test_undo_redo() {
+        let mut game = Game::default();
+        assert_eq!(game.undo(), None);
+        for game_move in ["f3", "e5", "g4", "Qh4"] {
+            game.make_move(&GameAction::from(game_move))
+                .expect(game_move);
+        }
+        assert_eq!(game.status, Some(GameOver::BlackCheckmates));
+
+        let mate = game.undo().unwrap();
+        assert_eq!(game.status, None);
+        assert_eq!(game.history().len(), 3);
+        game.undo().unwrap();
+        assert_eq!(game.redo(), Some(Move::Piece(G2, G4)));
+        assert_eq!(game.redo(), Some(mate));
+        assert_eq!(game.redo(), None);
+        assert_eq!(game.status, Some(GameOver::BlackCheckmates));
+
+        // a new move replaces the moves taken back
+        game.undo().unwrap();
+        game.make_move(&GameAction::from("d6")).unwrap();
+        assert_eq!(game.redo(), None);
+        assert_eq!(
+            game.history()
+                .map(|entry| entry.board.to_san(entry.chess_move))
+                .collect::<Vec<_>>(),
+            vec!["f3", "e5", "g4", "d6"]
+        );
+    }
+
+    #[test]
+    fn test_takeback() {
+        let mut game = Game::default();
+        assert_eq!(
+            game.make_move(&GameAction::RequestTakeback(Color::Black)),
+            Err(GameError::InvalidMove)
+        );
+        game.make_move(&GameAction::from("e4")).unwrap();
+        assert_eq!(
+            game.make_move(&GameAction::AcceptTakeback(Color::Black)),
+            Err(GameError::InvalidMove)
+        );
+        // only the player who made the move can ask to take it back
+        assert_eq!(
+            game.make_move(&GameAction::RequestTakeback(Color::Black)),
+            Err(GameError::InvalidMove)
+        );
+        game.make_move(&GameAction::RequestTakeback(Color::White))
+            .unwrap();
+        assert_eq!(game.takeback_requested, Some(Color::White));
+        // and they can't accept their own request
+        assert_eq!(
+            game.make_move(&GameAction::AcceptTakeback(Color::White)),
+            Err(GameError::InvalidMove)
+        );
+        game.make_move(&GameAction::AcceptTakeback(Color::Black))
+            .unwrap();
+        assert_eq!(game.board, Board::default());
+        assert_eq!(game.takeback_requested, None);
+
+        // a move declines the request
+        game.make_move(&GameAction::from("d4")).unwrap();
+        game.make_move(&GameAction::RequestTakeback(Color::White))
+            .unwrap();
+        game.make_move(&GameAction::from("d5")).unwrap();
+        assert_eq!(
+            game.make_move(&GameAction::AcceptTakeback(Color::Black)),
+            Err(GameError::InvalidMove)
+        );
+    }
+
+    #[test]
+    fn test_claim_threefold_repetition() {
+        let mut game = Game::default();
+        for game_move in ["Nc3", "Nc6", "Nb1", "Nb8", "Nc3", "Nc6", "Nb1", "Nb8"] {
+            game.make_move(&GameAction::from(game_move))
+                .expect(game_move);
+        }
+        assert_eq!(
+            game.make_move(&GameAction::ClaimDraw),
+            Ok(&Some(GameOver::ThreefoldRepetition))
+        );
+    }
 }
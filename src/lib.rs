@@ -22,13 +22,19 @@ mod board;
 pub use board::{Board, BoardBuilder};
 
 mod game;
-pub use game::{Game, GameAction, GameError, GameOver};
+pub use game::{Game, GameAction, GameError, GameOver, HistoryEntry};
+
+mod pgn;
+pub use pgn::{PgnError, PgnReader, SEVEN_TAG_ROSTER};
+
+mod tree;
+pub use tree::{GameNode, GameTree, NodeId};
 
 mod square;
 pub use square::{Square, EMPTY_SQUARE};
 
 mod piece;
-pub use piece::Piece;
+pub use piece::{centipawns_to_pawns, Piece, MAX_PHASE, PAWN_VALUE};
 
 mod position;
 pub use position::*;
@@ -36,6 +42,24 @@ pub use position::*;
 mod util;
 pub use util::*;
 
+mod search;
+pub use search::{Score, SearchLimits, SearchResult, Searcher};
+
+mod tt;
+pub use tt::{Bound, TranspositionTable, TtEntry, DEFAULT_TABLE_SIZE, SMALL_TABLE_SIZE};
+
+mod zobrist;
+
+mod params;
+pub use params::EvalParams;
+
+mod trace;
+pub use trace::{EvalTrace, TermTrace};
+
+mod pawns;
+
+mod mobility;
+
 pub const WHITE: Color = Color::White;
 pub const BLACK: Color = Color::Black;
 
@@ -49,8 +73,10 @@ pub enum GameResult {
     /// This stores the color of the winner.
     Victory(Color),
     /// The game is drawn. This can be a result of the current player
-    /// having no legal moves and not being in check, or because
-    /// both players have insufficient material on the board.
+    /// having no legal moves and not being in check, because
+    /// both players have insufficient material on the board, or because
+    /// seventy-five moves were played by each player without any capture
+    /// or pawn move.
     ///
     /// Insufficient material consists of:
     /// 1. The player only has a king
@@ -59,9 +85,10 @@ pub enum GameResult {
     /// 4. The player only has a king and a bishop
     /// 5. The player only has a king and two bishops
     ///
-    /// In a regular game of chess, threefold repetition also triggers
-    /// a stalemate, but this engine does not have builtin support for
-    /// threefold repetition detection yet.
+    /// A `Board` does not know which positions came before it, so
+    /// repetition draws are not reported here. Use `Game`, which keeps
+    /// the position history and detects threefold (claimable) and
+    /// fivefold (automatic) repetition.
     Stalemate,
     /// An illegal move was made. This can include many things,
     /// such as moving a piece through another piece, attempting
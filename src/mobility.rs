@@ -0,0 +1,126 @@
+// mobility and king safety terms of the evaluation.
+//
+// a piece's mobility is the number of squares it attacks that aren't
+// taken by its own pieces, found with its move generation. the king's
+// safety counts the squares around it attacked by the other player, using
+// `Board::is_threatened`, and the pawns sheltering it. king safety only
+// matters in the middlegame, when there are enough pieces left to mate.
+use super::*;
+
+// the number of squares each kind of piece usually controls, in the order
+// king, queen, rook, bishop, knight, pawn. kings and pawns aren't scored.
+const USUAL_MOBILITY: [i32; 6] = [0, 13, 7, 6, 4, 0];
+
+// score the squares `piece` controls, as (middlegame, endgame).
+pub(crate) fn mobility(params: &EvalParams, piece: Piece, board: &Board) -> (i32, i32) {
+    if piece.is_king() || piece.is_pawn() {
+        return (0, 0);
+    }
+    let (middlegame, endgame) = params.mobility[piece.get_kind_index()];
+    let usual = USUAL_MOBILITY[piece.get_kind_index()];
+    let squares = CoroutineIteratorAdapter::new(piece.get_moves(board))
+        .filter(|m| match m {
+            Move::Piece(_, to) => to.is_on_board() && piece.is_legal_attack(*to, board),
+            _ => false,
+        })
+        .count() as i32;
+    ((squares - usual) * middlegame, (squares - usual) * endgame)
+}
+
+// score how exposed the king of `color` is, in the middlegame.
+pub(crate) fn king_safety(params: &EvalParams, board: &Board, color: Color) -> i32 {
+    let king = match board.get_king_pos(color) {
+        Some(king) => king,
+        None => return 0,
+    };
+
+    let zone = [
+        king.next_left(),
+        king.next_right(),
+        king.next_above(),
+        king.next_below(),
+        king.next_left().next_above(),
+        king.next_left().next_below(),
+        king.next_right().next_above(),
+        king.next_right().next_below(),
+    ];
+    let attacked = zone
+        .iter()
+        .filter(|pos| pos.is_on_board() && board.is_threatened(**pos, color))
+        .count();
+
+    // the pawns on the two ranks in front of a king that hasn't left its
+    // first two ranks
+    let home = match color {
+        WHITE => king.get_row() <= 1,
+        BLACK => king.get_row() >= 6,
+    };
+    let mut shield = 0;
+    if home {
+        let ahead = king.pawn_up(color);
+        for pos in [ahead, ahead.pawn_up(color)].iter() {
+            for square in [pos.next_left(), *pos, pos.next_right()].iter() {
+                if square.is_on_board()
+                    && board.get_piece(*square) == Some(Piece::Pawn(color, *square))
+                {
+                    shield += 1;
+                }
+            }
+        }
+    }
+
+    params.king_zone_attacks[attacked] + params.pawn_shield * shield.min(3)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mobility() {
+        let params = EvalParams::default();
+        let (knight, rook) = (params.mobility[4], params.mobility[2]);
+        let board = Board::default();
+        // knights on their first squares only reach two
+        assert_eq!(
+            mobility(&params, Piece::Knight(WHITE, B1), &board),
+            (-2 * knight.0, -2 * knight.1)
+        );
+        // sliders are blocked by pieces in the way
+        assert_eq!(
+            mobility(&params, Piece::Rook(WHITE, A1), &board),
+            (-7 * rook.0, -7 * rook.1)
+        );
+        assert_eq!(mobility(&params, Piece::Pawn(WHITE, E2), &board), (0, 0));
+
+        // a centralized knight on an open board controls all eight squares
+        let board = parse_fen("4k3/8/8/8/3N4/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(
+            mobility(&params, Piece::Knight(WHITE, D4), &board),
+            (4 * knight.0, 4 * knight.1)
+        );
+    }
+
+    #[test]
+    fn test_king_safety() {
+        let params = EvalParams::default();
+        // a castled king behind its pawns is safe
+        let castled = parse_fen("4k3/8/8/8/8/8/5PPP/6K1 w - - 0 1").unwrap();
+        assert_eq!(
+            king_safety(&params, &castled, WHITE),
+            3 * params.pawn_shield
+        );
+
+        // without them, the rook attacks the squares around it
+        let exposed = parse_fen("4k3/8/8/8/8/8/r7/6K1 w - - 0 1").unwrap();
+        assert_eq!(
+            king_safety(&params, &exposed, WHITE),
+            params.king_zone_attacks[3]
+        );
+
+        // a king that hasn't castled still has its pawns in front of it
+        let board = Board::default();
+        assert_eq!(king_safety(&params, &board, WHITE), 3 * params.pawn_shield);
+        assert_eq!(king_safety(&params, &board, BLACK), 3 * params.pawn_shield);
+    }
+}
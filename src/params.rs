@@ -0,0 +1,440 @@
+use super::*;
+use core::str::FromStr;
+use near_sdk::{
+    borsh::{self, BorshDeserialize, BorshSerialize},
+    serde::{Deserialize, Serialize},
+};
+
+// the names of the kinds of pieces, in the order of the tables
+const KINDS: [&str; 6] = ["king", "queen", "rook", "bishop", "knight", "pawn"];
+
+/// The weights of the evaluation, in centipawns.
+///
+/// Tables indexed by the kind of piece are in the order king, queen, rook,
+/// bishop, knight, pawn, and terms with a pair of weights give one for the
+/// middlegame and one for the endgame, which are blended by the game
+/// phase. `EvalParams::default()` is the evaluation used by `value_for`
+/// and the search.
+///
+/// Parameter sets can be stored with Borsh or Serde, or as text with
+/// `Display` and `FromStr`. The text has one line for each field, its name
+/// followed by its values, such as `pawn_shield 10`; square tables are
+/// split into a line for each kind of piece, like `middlegame_knight`.
+/// Fields that are left out keep their default values, and lines starting
+/// with `#` are ignored, so a profile only needs the weights it changes.
+///
+/// ```rust
+/// use chess_engine::{Board, EvalParams, WHITE};
+///
+/// let mut params: EvalParams = "material 0 900 500 300 300 150".parse().unwrap();
+/// assert_eq!(params.material[5], 150);
+/// assert_eq!(params.to_string().parse::<EvalParams>().unwrap(), params);
+///
+/// params.material[5] = 100;
+/// let board = Board::default();
+/// assert_eq!(board.evaluate_with(&params, WHITE), board.value_for(WHITE));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, BorshDeserialize, BorshSerialize, Deserialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct EvalParams {
+    /// The material value of each kind of piece. Kings can't be captured,
+    /// so theirs only matters in variants where one player has no king,
+    /// and is 0 by default.
+    pub material: [i32; 6],
+    /// The weight of each square for each kind of piece in the middlegame,
+    /// from white's side of the board with the eighth rank first. Black's
+    /// weights are the same with the ranks reversed.
+    pub middlegame_squares: [[[i32; 8]; 8]; 6],
+    /// Like `middlegame_squares`, for the endgame.
+    pub endgame_squares: [[[i32; 8]; 8]; 6],
+    /// How much each kind of piece adds to the game phase.
+    pub phase: [i32; 6],
+    /// For each pawn on a file after the first.
+    pub doubled_pawn: (i32, i32),
+    /// For a pawn without pawns of its color on the files next to it.
+    pub isolated_pawn: (i32, i32),
+    /// For a pawn that has been left behind by the pawns that could
+    /// defend it, and can't advance without being taken.
+    pub backward_pawn: (i32, i32),
+    /// For a passed pawn, by its rank counted from its own side. This is
+    /// halved when a piece stands in front of it.
+    pub passed_pawn: [(i32, i32); 8],
+    /// For each square a piece controls, more or less than it usually
+    /// does.
+    pub mobility: [(i32, i32); 6],
+    /// For the number of attacked squares around a king, in the
+    /// middlegame.
+    pub king_zone_attacks: [i32; 9],
+    /// For each pawn sheltering a king on its own side, in the middlegame.
+    pub pawn_shield: i32,
+}
+
+impl EvalParams {
+    /// The weights used by `value_for` and the search.
+    pub const DEFAULT: Self = Self {
+        material: [0, 900, 500, 300, 300, PAWN_VALUE],
+        middlegame_squares: [
+            // king
+            [
+                [-30, -40, -40, -50, -50, -40, -40, -30],
+                [-30, -40, -40, -50, -50, -40, -40, -30],
+                [-30, -40, -40, -50, -50, -40, -40, -30],
+                [-30, -40, -40, -50, -50, -40, -40, -30],
+                [-20, -30, -30, -40, -40, -30, -30, -20],
+                [-10, -20, -20, -20, -20, -20, -20, -10],
+                [20, 20, 0, 0, 0, 0, 20, 20],
+                [20, 30, 10, 0, 0, 10, 30, 20],
+            ],
+            // queen
+            [
+                [-20, -10, -10, -5, -5, -10, -10, -20],
+                [-10, 0, 0, 0, 0, 0, 0, -10],
+                [-10, 0, 5, 5, 5, 5, 0, -10],
+                [-5, 0, 5, 5, 5, 5, 0, -5],
+                [0, 0, 5, 5, 5, 5, 0, -5],
+                [-10, 5, 5, 5, 5, 5, 0, -10],
+                [-10, 0, 5, 0, 0, 0, 0, -10],
+                [-10, 0, -10, -5, -5, -5, -10, -20],
+            ],
+            // rook
+            [
+                [0, 0, 0, 0, 0, 0, 0, 0],
+                [5, 10, 10, 10, 10, 10, 10, 5],
+                [-5, 0, 0, 0, 0, 0, 0, -5],
+                [-5, 0, 0, 0, 0, 0, 0, -5],
+                [-5, 0, 0, 0, 0, 0, 0, -5],
+                [-5, 0, 0, 0, 0, 0, 0, -5],
+                [-5, 0, 0, 0, 0, 0, 0, -5],
+                [0, 0, 0, 5, 5, 0, 0, 0],
+            ],
+            // bishop
+            [
+                [-20, -10, -10, -10, -10, -10, -10, -20],
+                [-10, 0, 0, 0, 0, 0, 0, -10],
+                [-10, 0, 5, 10, 10, 5, 0, -10],
+                [-10, 5, 5, 10, 10, 5, 5, -10],
+                [-10, 0, 10, 10, 10, 10, 0, -10],
+                [-10, 10, 10, 10, 10, 10, 10, -10],
+                [-10, 5, 0, 0, 0, 0, 5, -10],
+                [-20, -10, -10, -10, -10, -10, -10, -20],
+            ],
+            // knight
+            [
+                [-50, -40, -30, -30, -30, -30, -40, -50],
+                [-40, -20, 0, 0, 0, 0, -20, -40],
+                [-30, 0, 10, 15, 15, 10, 0, -30],
+                [-30, 5, 15, 20, 20, 15, 5, -30],
+                [-30, 0, 15, 20, 20, 15, 0, -30],
+                [-30, 5, 10, 15, 15, 10, 5, -30],
+                [-40, -20, 0, 5, 5, 0, -20, -40],
+                [-50, -40, -30, -30, -30, -30, -40, -50],
+            ],
+            // pawn
+            [
+                [0, 0, 0, 0, 0, 0, 0, 0],
+                [50, 50, 50, 50, 50, 50, 50, 50],
+                [10, 10, 20, 30, 30, 20, 10, 10],
+                [5, 5, 10, 25, 25, 10, 5, 5],
+                [0, 0, 0, 20, 20, 0, 0, 0],
+                [5, -5, -10, 0, 0, -10, -5, 5],
+                [5, 15, -10, -20, -20, 10, 15, 5],
+                [0, 0, 0, 0, 0, 0, 0, 0],
+            ],
+        ],
+        endgame_squares: [
+            // king
+            [
+                [-50, -40, -30, -20, -20, -30, -40, -50],
+                [-30, -20, -10, 0, 0, -10, -20, -30],
+                [-30, -10, 20, 30, 30, 20, -10, -30],
+                [-30, -10, 30, 40, 40, 30, -10, -30],
+                [-30, -10, 30, 40, 40, 30, -10, -30],
+                [-30, -10, 20, 30, 30, 20, -10, -30],
+                [-30, -30, 0, 0, 0, 0, -30, -30],
+                [-50, -30, -30, -30, -30, -30, -30, -50],
+            ],
+            // queen
+            [
+                [-20, -10, -10, -5, -5, -10, -10, -20],
+                [-10, 0, 0, 0, 0, 0, 0, -10],
+                [-10, 0, 5, 5, 5, 5, 0, -10],
+                [-5, 0, 5, 10, 10, 5, 0, -5],
+                [-5, 0, 5, 10, 10, 5, 0, -5],
+                [-10, 0, 5, 5, 5, 5, 0, -10],
+                [-10, 0, 0, 0, 0, 0, 0, -10],
+                [-20, -10, -10, -5, -5, -10, -10, -20],
+            ],
+            // rook
+            [
+                [0, 0, 0, 0, 0, 0, 0, 0],
+                [10, 10, 10, 10, 10, 10, 10, 10],
+                [0, 0, 0, 0, 0, 0, 0, 0],
+                [0, 0, 0, 0, 0, 0, 0, 0],
+                [0, 0, 0, 0, 0, 0, 0, 0],
+                [0, 0, 0, 0, 0, 0, 0, 0],
+                [0, 0, 0, 0, 0, 0, 0, 0],
+                [0, 0, 0, 0, 0, 0, 0, 0],
+            ],
+            // bishop
+            [
+                [-20, -10, -10, -10, -10, -10, -10, -20],
+                [-10, 0, 0, 0, 0, 0, 0, -10],
+                [-10, 0, 5, 10, 10, 5, 0, -10],
+                [-10, 5, 10, 15, 15, 10, 5, -10],
+                [-10, 5, 10, 15, 15, 10, 5, -10],
+                [-10, 0, 5, 10, 10, 5, 0, -10],
+                [-10, 0, 0, 0, 0, 0, 0, -10],
+                [-20, -10, -10, -10, -10, -10, -10, -20],
+            ],
+            // knight
+            [
+                [-50, -40, -30, -30, -30, -30, -40, -50],
+                [-40, -20, 0, 0, 0, 0, -20, -40],
+                [-30, 0, 10, 15, 15, 10, 0, -30],
+                [-30, 5, 15, 20, 20, 15, 5, -30],
+                [-30, 5, 15, 20, 20, 15, 5, -30],
+                [-30, 0, 10, 15, 15, 10, 0, -30],
+                [-40, -20, 0, 0, 0, 0, -20, -40],
+                [-50, -40, -30, -30, -30, -30, -40, -50],
+            ],
+            // pawn
+            [
+                [0, 0, 0, 0, 0, 0, 0, 0],
+                [80, 80, 80, 80, 80, 80, 80, 80],
+                [50, 50, 50, 50, 50, 50, 50, 50],
+                [30, 30, 30, 30, 30, 30, 30, 30],
+                [15, 15, 15, 15, 15, 15, 15, 15],
+                [5, 5, 5, 5, 5, 5, 5, 5],
+                [0, 0, 0, 0, 0, 0, 0, 0],
+                [0, 0, 0, 0, 0, 0, 0, 0],
+            ],
+        ],
+        phase: [0, 4, 2, 1, 1, 0],
+        doubled_pawn: (-10, -20),
+        isolated_pawn: (-10, -15),
+        backward_pawn: (-8, -10),
+        passed_pawn: [
+            (0, 0),
+            (5, 10),
+            (5, 15),
+            (10, 25),
+            (20, 45),
+            (35, 75),
+            (60, 120),
+            (0, 0),
+        ],
+        mobility: [(0, 0), (1, 2), (2, 4), (5, 5), (4, 4), (0, 0)],
+        king_zone_attacks: [0, -5, -15, -30, -50, -75, -100, -130, -160],
+        pawn_shield: 10,
+    };
+
+    /// The material and positional value of `piece` in the middlegame.
+    #[inline]
+    pub fn get_middlegame_value(&self, piece: Piece) -> i32 {
+        self.material[piece.get_kind_index()]
+            + square_weight(&self.middlegame_squares[piece.get_kind_index()], piece)
+    }
+
+    /// The material and positional value of `piece` in the endgame.
+    #[inline]
+    pub fn get_endgame_value(&self, piece: Piece) -> i32 {
+        self.material[piece.get_kind_index()]
+            + square_weight(&self.endgame_squares[piece.get_kind_index()], piece)
+    }
+
+    /// How much `piece` adds to the game phase of a board.
+    #[inline]
+    pub fn get_phase_weight(&self, piece: Piece) -> i32 {
+        self.phase[piece.get_kind_index()]
+    }
+
+    /// The game phase with every knight, bishop, rook and queen of the
+    /// starting position on the board.
+    pub fn get_max_phase(&self) -> i32 {
+        // two of each minor piece and rook, and a queen, for each player
+        4 * (self.phase[2] + self.phase[3] + self.phase[4]) + 2 * self.phase[1]
+    }
+
+    /// Every weight, grouped by the name of its line in the text format,
+    /// so the weights can be changed one at a time, as by a tuner.
+    pub fn fields_mut(&mut self) -> Vec<(String, Vec<&mut i32>)> {
+        let Self {
+            material,
+            middlegame_squares,
+            endgame_squares,
+            phase,
+            doubled_pawn,
+            isolated_pawn,
+            backward_pawn,
+            passed_pawn,
+            mobility,
+            king_zone_attacks,
+            pawn_shield,
+        } = self;
+
+        let mut fields = vec![(String::from("material"), material.iter_mut().collect())];
+        for (prefix, tables) in [
+            ("middlegame", middlegame_squares),
+            ("endgame", endgame_squares),
+        ] {
+            for (kind, table) in KINDS.iter().zip(tables.iter_mut()) {
+                fields.push((
+                    format!("{}_{}", prefix, kind),
+                    table.iter_mut().flatten().collect(),
+                ));
+            }
+        }
+        fields.push((String::from("phase"), phase.iter_mut().collect()));
+        for (name, (middlegame, endgame)) in [
+            ("doubled_pawn", doubled_pawn),
+            ("isolated_pawn", isolated_pawn),
+            ("backward_pawn", backward_pawn),
+        ] {
+            fields.push((String::from(name), vec![middlegame, endgame]));
+        }
+        for (name, pairs) in [
+            ("passed_pawn", &mut passed_pawn[..]),
+            ("mobility", &mut mobility[..]),
+        ] {
+            fields.push((
+                String::from(name),
+                pairs
+                    .iter_mut()
+                    .flat_map(|(middlegame, endgame)| vec![middlegame, endgame])
+                    .collect(),
+            ));
+        }
+        fields.push((
+            String::from("king_zone_attacks"),
+            king_zone_attacks.iter_mut().collect(),
+        ));
+        fields.push((String::from("pawn_shield"), vec![pawn_shield]));
+        fields
+    }
+}
+
+impl Default for EvalParams {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl core::fmt::Display for EvalParams {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> Result<(), core::fmt::Error> {
+        for (name, values) in self.clone().fields_mut() {
+            write!(f, "{}", name)?;
+            for value in values {
+                write!(f, " {}", value)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for EvalParams {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut params = Self::default();
+        let mut fields = params.fields_mut();
+        for line in s.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            let name = words.next().unwrap_or_default();
+            let (_, slots) = fields
+                .iter_mut()
+                .find(|(field, _)| field == name)
+                .ok_or_else(|| format!("unknown parameter {}", name))?;
+            let values = words
+                .map(|word| word.parse::<i32>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| format!("invalid value for {}", name))?;
+            if values.len() != slots.len() {
+                return Err(format!(
+                    "{} takes {} values, not {}",
+                    name,
+                    slots.len(),
+                    values.len()
+                ));
+            }
+            for (slot, value) in slots.iter_mut().zip(values) {
+                **slot = value;
+            }
+        }
+        Ok(params)
+    }
+}
+
+// the weight of the square of `piece` in a table seen from white's side
+#[inline]
+fn square_weight(table: &[[i32; 8]; 8], piece: Piece) -> i32 {
+    let pos = piece.get_pos();
+    let row = match piece.get_color() {
+        WHITE => 7 - pos.get_row(),
+        BLACK => pos.get_row(),
+    };
+    table[row as usize][pos.get_col() as usize]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eval_params_text() {
+        let params = EvalParams::default();
+        assert_eq!(params.get_max_phase(), MAX_PHASE);
+        assert_eq!(params.to_string().parse::<EvalParams>().unwrap(), params);
+
+        let profile = "# a profile that only changes some weights\n\
+                       pawn_shield 25\n\
+                       \n\
+                       doubled_pawn -30 -40\n";
+        let custom: EvalParams = profile.parse().unwrap();
+        assert_eq!(custom.pawn_shield, 25);
+        assert_eq!(custom.doubled_pawn, (-30, -40));
+        assert_eq!(custom.material, params.material);
+
+        assert_eq!(
+            "queen_value 900".parse::<EvalParams>(),
+            Err(String::from("unknown parameter queen_value"))
+        );
+        assert_eq!(
+            "pawn_shield 1 2".parse::<EvalParams>(),
+            Err(String::from("pawn_shield takes 1 values, not 2"))
+        );
+        assert!("pawn_shield ten".parse::<EvalParams>().is_err());
+    }
+
+    #[test]
+    fn test_evaluate_with() {
+        let board =
+            parse_fen("rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2").unwrap();
+        let params = EvalParams::default();
+        assert_eq!(board.evaluate_with(&params, WHITE), board.value_for(WHITE));
+        assert_eq!(board.evaluate_with(&params, BLACK), board.value_for(BLACK));
+
+        // without weights for where knights stand, white's developed
+        // knight is no better than black's undeveloped ones
+        let mut params = EvalParams::default();
+        params.mobility[4] = (0, 0);
+        params.middlegame_squares[4] = [[0; 8]; 8];
+        params.endgame_squares[4] = [[0; 8]; 8];
+        let without_knights = board.evaluate_with(&params, WHITE);
+        assert!(without_knights < board.value_for(WHITE));
+
+        // a searcher can use them too
+        let result = Searcher::default().set_params(&params).search(
+            &board,
+            SearchLimits {
+                depth: Some(1),
+                ..SearchLimits::default()
+            },
+            |_| {},
+        );
+        assert_eq!(result.depth, 1);
+    }
+}
@@ -0,0 +1,312 @@
+// pawn structure terms of the evaluation, and a cache for them.
+//
+// the terms only depend on where the pawns stand, so they are cached by a
+// Zobrist hash of the pawns alone, which stays the same for far more of
+// the search than the hash of the whole position. a blockade depends on
+// the other pieces too, so only the squares of the passed pawns are cached
+// for it, and the pieces standing in front of them are checked every time.
+//
+// pawns are kept in bitboards, with bit `row * 8 + col` set for a pawn on
+// that square, so the first bit is a1 and the last is h8.
+use super::*;
+
+// entries in the cache kept by a search
+pub(crate) const PAWN_TABLE_SIZE: usize = 1024;
+
+const FILE_A: u64 = 0x0101_0101_0101_0101;
+
+// the pawn structure terms of a board.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct PawnStructure {
+    // the (middlegame, endgame) score of each player's pawns, white's first
+    pub(crate) scores: [(i32, i32); 2],
+    // the passed pawns of both players
+    passed: u64,
+}
+
+// a cache of pawn structures, indexed by the hash of the pawns. the
+// structures depend on the evaluation parameters, so a table must only be
+// used with one set of them.
+//
+// an empty slot has the key and the structure of a board without pawns,
+// so it doesn't need to be told apart from a stored one.
+pub(crate) struct PawnTable {
+    entries: Vec<(u64, PawnStructure)>,
+}
+
+impl PawnTable {
+    pub(crate) fn new(len: usize) -> Self {
+        Self {
+            entries: vec![(0, PawnStructure::default()); len],
+        }
+    }
+
+    // the number of structures the table can hold.
+    #[cfg(test)]
+    pub(crate) fn capacity(&self) -> usize {
+        self.entries.len()
+    }
+
+    // forget every stored structure.
+    pub(crate) fn clear(&mut self) {
+        for entry in self.entries.iter_mut() {
+            *entry = (0, PawnStructure::default());
+        }
+    }
+
+    // the structure of the pawns with hash `key`, evaluating and storing
+    // it if it isn't cached.
+    pub(crate) fn get(
+        &mut self,
+        params: &EvalParams,
+        key: u64,
+        white: u64,
+        black: u64,
+    ) -> PawnStructure {
+        if self.entries.is_empty() {
+            return evaluate_pawns(params, white, black);
+        }
+        let index = (key % self.entries.len() as u64) as usize;
+        let entry = &mut self.entries[index];
+        if entry.0 != key {
+            *entry = (key, evaluate_pawns(params, white, black));
+        }
+        entry.1
+    }
+}
+
+// the bit for `pos` in a bitboard.
+#[inline]
+pub(crate) fn square_bit(pos: Position) -> u64 {
+    1 << (pos.get_row() * 8 + pos.get_col())
+}
+
+// score doubled, isolated, backward and passed pawns.
+pub(crate) fn evaluate_pawns(params: &EvalParams, white: u64, black: u64) -> PawnStructure {
+    let mut structure = PawnStructure::default();
+    for &(side, color, own, other) in [(0, WHITE, white, black), (1, BLACK, black, white)].iter() {
+        let score = &mut structure.scores[side];
+        let mut add = |(middlegame, endgame): (i32, i32), times: i32| {
+            score.0 += middlegame * times;
+            score.1 += endgame * times;
+        };
+
+        for col in 0..8 {
+            let count = (own & FILE_A << col).count_ones() as i32;
+            if count > 1 {
+                add(params.doubled_pawn, count - 1);
+            }
+        }
+
+        for square in bits(own) {
+            let (row, col) = (square / 8, square % 8);
+            let file = FILE_A << col;
+            let neighbours = adjacent_files(col);
+            let ahead = rows_ahead(color, row);
+
+            if own & neighbours == 0 {
+                add(params.isolated_pawn, 1);
+            } else if own & neighbours & !ahead == 0
+                && front_is_attacked(color, row, other, neighbours)
+            {
+                // every pawn that could defend it has moved past it, and
+                // it can't advance without being taken
+                add(params.backward_pawn, 1);
+            }
+
+            if other & (file | neighbours) & ahead == 0 {
+                structure.passed |= 1 << square;
+                add(params.passed_pawn[relative_rank(color, row)], 1);
+            }
+        }
+    }
+    structure
+}
+
+// take back half of the bonus of each passed pawn with a piece in front
+// of it, for each player, white first.
+pub(crate) fn blockade(
+    params: &EvalParams,
+    structure: &PawnStructure,
+    white: u64,
+    occupied: u64,
+) -> [(i32, i32); 2] {
+    let mut penalties = [(0, 0); 2];
+    for square in bits(structure.passed) {
+        let (side, color, front) = if white & 1 << square != 0 {
+            (0, WHITE, square.checked_add(8).filter(|&front| front < 64))
+        } else {
+            (1, BLACK, square.checked_sub(8))
+        };
+        // a pawn on the last rank, from a FEN that put it there, has no
+        // square in front of it to block
+        let front = match front {
+            Some(front) => front,
+            None => continue,
+        };
+        if occupied & 1 << front != 0 {
+            let (middlegame, endgame) = params.passed_pawn[relative_rank(color, square / 8)];
+            penalties[side].0 -= middlegame / 2;
+            penalties[side].1 -= endgame / 2;
+        }
+    }
+    penalties
+}
+
+// the squares set in a bitboard, lowest first.
+fn bits(mut bitboard: u64) -> impl Iterator<Item = u32> {
+    core::iter::from_fn(move || {
+        if bitboard == 0 {
+            return None;
+        }
+        let square = bitboard.trailing_zeros();
+        bitboard &= bitboard - 1;
+        Some(square)
+    })
+}
+
+fn adjacent_files(col: u32) -> u64 {
+    let mut files = 0;
+    if col > 0 {
+        files |= FILE_A << (col - 1);
+    }
+    if col < 7 {
+        files |= FILE_A << (col + 1);
+    }
+    files
+}
+
+// the rows in front of a pawn of `color` on `row`.
+fn rows_ahead(color: Color, row: u32) -> u64 {
+    match color {
+        WHITE if row == 7 => 0,
+        WHITE => !0 << ((row + 1) * 8),
+        BLACK => (1 << (row * 8)) - 1,
+    }
+}
+
+// whether an enemy pawn attacks the square in front of a pawn of `color`.
+fn front_is_attacked(color: Color, row: u32, other: u64, neighbours: u64) -> bool {
+    let attacker_row = match color {
+        WHITE => row + 2,
+        BLACK if row < 2 => return false,
+        BLACK => row - 2,
+    };
+    attacker_row < 8 && other & neighbours & 0xff << (attacker_row * 8) != 0
+}
+
+fn relative_rank(color: Color, row: u32) -> usize {
+    match color {
+        WHITE => row as usize,
+        BLACK => 7 - row as usize,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pawns_of(board: &Board, color: Color) -> u64 {
+        (0..8)
+            .flat_map(|row| (0..8).map(move |col| Position::new(row, col)))
+            .filter(|&pos| board.get_piece(pos) == Some(Piece::Pawn(color, pos)))
+            .map(square_bit)
+            .fold(0, |bitboard, bit| bitboard | bit)
+    }
+
+    fn structure(fen: &str) -> PawnStructure {
+        let board = parse_fen(fen).unwrap();
+        evaluate_pawns(
+            &EvalParams::DEFAULT,
+            pawns_of(&board, WHITE),
+            pawns_of(&board, BLACK),
+        )
+    }
+
+    #[test]
+    fn test_pawn_structure() {
+        // the start position is symmetric
+        let start = structure("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        assert_eq!(start.scores[0], start.scores[1]);
+        assert_eq!(start.passed, 0);
+
+        // doubled and isolated pawns are worse than connected ones
+        let connected = structure("4k3/3p4/8/8/8/8/3PP3/4K3 w - - 0 1");
+        let doubled = structure("4k3/3p4/8/8/8/4P3/4P3/4K3 w - - 0 1");
+        assert!(doubled.scores[0].1 < connected.scores[0].1);
+
+        // d2 can't be defended, and d3 is attacked by e4
+        let backward = structure("4k3/8/8/8/4p3/2P5/3P4/4K3 w - - 0 1");
+        let supported = structure("4k3/8/8/8/4p3/8/2PP4/4K3 w - - 0 1");
+        assert_eq!(
+            backward.scores[0].0,
+            supported.scores[0].0 + EvalParams::DEFAULT.backward_pawn.0
+        );
+
+        // passed pawns are worth more the further they've run
+        let passed = structure("4k3/8/8/8/8/8/P7/4K3 w - - 0 1");
+        let advanced = structure("4k3/8/P7/8/8/8/8/4K3 w - - 0 1");
+        assert_eq!(passed.passed, square_bit(A2));
+        assert!(advanced.scores[0].1 > passed.scores[0].1);
+        let opposed = structure("4k3/1p6/8/8/8/8/P7/4K3 w - - 0 1");
+        assert_eq!(opposed.passed, 0);
+    }
+
+    #[test]
+    fn test_blockade() {
+        let params = EvalParams::default();
+        let board = parse_fen("4k3/8/P7/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let white = pawns_of(&board, WHITE);
+        let passed = evaluate_pawns(&params, white, 0);
+        assert_eq!(blockade(&params, &passed, white, white), [(0, 0); 2]);
+        let (middlegame, endgame) = params.passed_pawn[5];
+        assert_eq!(
+            blockade(&params, &passed, white, white | square_bit(A7)),
+            [(-middlegame / 2, -endgame / 2), (0, 0)]
+        );
+
+        // pawns on the last rank can't be blocked
+        for &fen in [
+            "4k3/8/8/8/8/8/8/p3K3 w - - 0 1",
+            "P3k3/8/8/8/8/8/8/4K3 w - - 0 1",
+        ]
+        .iter()
+        {
+            let board = parse_fen(fen).unwrap();
+            let (white, black) = (pawns_of(&board, WHITE), pawns_of(&board, BLACK));
+            let passed = evaluate_pawns(&params, white, black);
+            assert_eq!(
+                blockade(&params, &passed, white, white | black),
+                [(0, 0); 2]
+            );
+        }
+
+        // the value of the board includes the pawn structure
+        let board = parse_fen("4k3/8/8/8/8/8/P7/4K3 w - - 0 1").unwrap();
+        assert_eq!(
+            board.value_for(WHITE),
+            Piece::Pawn(WHITE, A2).get_endgame_value()
+                + params.isolated_pawn.1
+                + params.passed_pawn[1].1
+        );
+    }
+
+    #[test]
+    fn test_pawn_table() {
+        let params = EvalParams::default();
+        let board = Board::default();
+        let (white, black) = (pawns_of(&board, WHITE), pawns_of(&board, BLACK));
+        let mut table = PawnTable::new(16);
+        let key = 0x1234;
+        let structure = evaluate_pawns(&params, white, black);
+        assert_eq!(table.get(&params, key, white, black), structure);
+        // a stored entry is returned without evaluating the pawns again
+        assert_eq!(table.get(&params, key, 0, 0), structure);
+        table.clear();
+        assert_eq!(table.get(&params, key, 0, 0), PawnStructure::default());
+        assert_eq!(
+            PawnTable::new(0).get(&params, key, 0, 0),
+            PawnStructure::default()
+        );
+    }
+}
@@ -0,0 +1,670 @@
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use crate::board::Board;
+use crate::game::{Game, GameOver};
+use crate::util::{format_fen, parse_fen, parse_san_move, SanError};
+use crate::WHITE;
+
+// the tags every pgn game has, written first and in this order
+pub const SEVEN_TAG_ROSTER: [&str; 7] =
+    ["Event", "Site", "Date", "Round", "White", "Black", "Result"];
+
+// longest movetext line written, as recommended for pgn export format
+const MAX_LINE_LENGTH: usize = 79;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PgnError {
+    // text that is not valid pgn, with a description
+    Malformed(String),
+    // the FEN tag does not hold a valid position
+    InvalidPosition,
+    // a move that cannot be played, with its text and the reason
+    InvalidMove(String, SanError),
+}
+
+impl core::fmt::Display for PgnError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> Result<(), core::fmt::Error> {
+        match self {
+            Self::Malformed(reason) => write!(f, "malformed pgn: {}", reason),
+            Self::InvalidPosition => write!(f, "invalid FEN tag"),
+            Self::InvalidMove(san, error) => write!(f, "{} `{}`", error, san),
+        }
+    }
+}
+
+// a single lexical element of pgn text
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Token<'a> {
+    // [Name "value"], with escapes removed from the value
+    Tag(&'a str, String),
+    // "12." or "12...", only used for readability
+    MoveNumber,
+    San(&'a str),
+    // $1, or a suffix like "!?" converted to its glyph number
+    Nag(u8),
+    Comment(&'a str),
+    StartVariation,
+    EndVariation,
+    // "1-0", "0-1", "1/2-1/2" or "*"
+    Result(&'a str),
+}
+
+// split pgn text into tokens
+pub(crate) fn tokenize(pgn: &str) -> Result<Vec<Token<'_>>, PgnError> {
+    let mut tokens = vec![];
+    let bytes = pgn.as_bytes();
+    let mut i = 0;
+
+    // find the end of a run of bytes matching `f`, starting at `start`
+    let scan = |start: usize, f: &dyn Fn(u8) -> bool| {
+        let mut end = start;
+        while end < bytes.len() && f(bytes[end]) {
+            end += 1;
+        }
+        end
+    };
+
+    while i < bytes.len() {
+        let c = bytes[i];
+        match c {
+            // escape mechanism, the whole line is ignored
+            b'%' if i == 0 || bytes[i - 1] == b'\n' => {
+                i = scan(i, &|c| c != b'\n');
+            }
+            c if c.is_ascii_whitespace() => i += 1,
+            b'[' => {
+                let name_start = scan(i + 1, &|c| c.is_ascii_whitespace());
+                let name_end = scan(name_start, &|c| c.is_ascii_alphanumeric() || c == b'_');
+                let quote = scan(name_end, &|c| c.is_ascii_whitespace());
+                if name_start == name_end || bytes.get(quote) != Some(&b'"') {
+                    return Err(PgnError::Malformed(String::from("invalid tag pair")));
+                }
+
+                let mut value = String::new();
+                let mut chars = pgn[quote + 1..].char_indices();
+                let mut value_end = None;
+                while let Some((offset, c)) = chars.next() {
+                    match c {
+                        '\\' => {
+                            if let Some((_, escaped)) = chars.next() {
+                                value.push(escaped);
+                            }
+                        }
+                        '"' => {
+                            value_end = Some(quote + 1 + offset);
+                            break;
+                        }
+                        c => value.push(c),
+                    }
+                }
+                let close = match value_end {
+                    Some(value_end) => scan(value_end + 1, &|c| c.is_ascii_whitespace()),
+                    None => return Err(PgnError::Malformed(String::from("unterminated tag"))),
+                };
+                if bytes.get(close) != Some(&b']') {
+                    return Err(PgnError::Malformed(String::from("unterminated tag")));
+                }
+
+                tokens.push(Token::Tag(&pgn[name_start..name_end], value));
+                i = close + 1;
+            }
+            b'{' => {
+                let end = scan(i + 1, &|c| c != b'}');
+                if end == bytes.len() {
+                    return Err(PgnError::Malformed(String::from("unterminated comment")));
+                }
+                tokens.push(Token::Comment(pgn[i + 1..end].trim()));
+                i = end + 1;
+            }
+            b';' => {
+                let end = scan(i + 1, &|c| c != b'\n');
+                tokens.push(Token::Comment(pgn[i + 1..end].trim()));
+                i = end;
+            }
+            b'(' => {
+                tokens.push(Token::StartVariation);
+                i += 1;
+            }
+            b')' => {
+                tokens.push(Token::EndVariation);
+                i += 1;
+            }
+            b'*' => {
+                tokens.push(Token::Result("*"));
+                i += 1;
+            }
+            b'$' => {
+                let end = scan(i + 1, &|c| c.is_ascii_digit());
+                match pgn[i + 1..end].parse() {
+                    Ok(nag) => tokens.push(Token::Nag(nag)),
+                    Err(_) => return Err(PgnError::Malformed(String::from("invalid NAG"))),
+                }
+                i = end;
+            }
+            b'!' | b'?' => {
+                let end = scan(i, &|c| c == b'!' || c == b'?');
+                let nag = match &pgn[i..end] {
+                    "!" => 1,
+                    "?" => 2,
+                    "!!" => 3,
+                    "??" => 4,
+                    "!?" => 5,
+                    "?!" => 6,
+                    other => {
+                        return Err(PgnError::Malformed(format!(
+                            "unknown annotation `{}`",
+                            other
+                        )))
+                    }
+                };
+                tokens.push(Token::Nag(nag));
+                i = end;
+            }
+            c if c.is_ascii_alphanumeric() => {
+                let end = scan(i, &|c| {
+                    c.is_ascii_alphanumeric()
+                        || matches!(c, b'_' | b'+' | b'#' | b'=' | b':' | b'-' | b'/')
+                });
+                let symbol = &pgn[i..end];
+                if matches!(symbol, "1-0" | "0-1" | "1/2-1/2") {
+                    tokens.push(Token::Result(symbol));
+                    i = end;
+                } else if symbol.bytes().all(|c| c.is_ascii_digit()) {
+                    tokens.push(Token::MoveNumber);
+                    i = scan(end, &|c| c == b'.');
+                } else {
+                    tokens.push(Token::San(symbol));
+                    i = end;
+                }
+            }
+            _ => {
+                let c = pgn[i..].chars().next().unwrap_or('?');
+                return Err(PgnError::Malformed(format!("unexpected character `{}`", c)));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+// pgn result token for a finished game
+fn result_token(status: &Option<GameOver>) -> &'static str {
+    match status {
+        None => "*",
+        Some(GameOver::WhiteCheckmates)
+        | Some(GameOver::BlackResigns)
+        | Some(GameOver::WhiteWins) => "1-0",
+        Some(GameOver::BlackCheckmates)
+        | Some(GameOver::WhiteResigns)
+        | Some(GameOver::BlackWins) => "0-1",
+        Some(_) => "1/2-1/2",
+    }
+}
+
+// game status for a result token, for games that end without checkmate
+// or stalemate, where pgn doesn't say why
+fn status_for_result(result: &str) -> Option<GameOver> {
+    match result {
+        "1-0" => Some(GameOver::WhiteWins),
+        "0-1" => Some(GameOver::BlackWins),
+        "1/2-1/2" => Some(GameOver::Drawn),
+        _ => None,
+    }
+}
+
+// starting position from the SetUp/FEN tags, standard if there are none
+pub(crate) fn starting_board(tags: &[(String, String)]) -> Result<Board, PgnError> {
+    match tags.iter().find(|(name, _)| name == "FEN") {
+        Some((_, fen)) => parse_fen(fen).map_err(|_| PgnError::InvalidPosition),
+        None => Ok(Board::default()),
+    }
+}
+
+// value of the tag `name` among `tags`
+pub(crate) fn get_tag<'a>(tags: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    tags.iter()
+        .find(|(tag, _)| tag == name)
+        .map(|(_, value)| value.as_str())
+}
+
+// add a tag, or replace the value of an existing one
+pub(crate) fn set_tag(tags: &mut Vec<(String, String)>, name: &str, value: &str) {
+    match tags.iter_mut().find(|(tag, _)| tag == name) {
+        Some((_, old)) => *old = String::from(value),
+        None => tags.push((String::from(name), String::from(value))),
+    }
+}
+
+// write a [Name "value"] line, escaping the value
+pub(crate) fn write_tag(pgn: &mut String, name: &str, value: &str) {
+    pgn.push('[');
+    pgn.push_str(name);
+    pgn.push_str(" \"");
+    for c in value.chars() {
+        if c == '"' || c == '\\' {
+            pgn.push('\\');
+        }
+        pgn.push(c);
+    }
+    pgn.push_str("\"]\n");
+}
+
+// write the tag section for a game starting from `start`, with the
+// seven tag roster first and SetUp/FEN for non-standard positions
+pub(crate) fn write_tags(pgn: &mut String, tags: &[(String, String)], start: &Board, result: &str) {
+    for name in SEVEN_TAG_ROSTER {
+        let value = match name {
+            "Result" => result,
+            "Date" => get_tag(tags, name).unwrap_or("????.??.??"),
+            _ => get_tag(tags, name).unwrap_or("?"),
+        };
+        write_tag(pgn, name, value);
+    }
+
+    let fen = format_fen(start).unwrap_or_default();
+    if fen != format_fen(&Board::default()).unwrap_or_default() {
+        write_tag(pgn, "SetUp", "1");
+        write_tag(pgn, "FEN", &fen);
+    }
+
+    for (name, value) in tags {
+        if !SEVEN_TAG_ROSTER.contains(&name.as_str()) && name != "SetUp" && name != "FEN" {
+            write_tag(pgn, name, value);
+        }
+    }
+}
+
+// add a comment to movetext. a brace comment ends at the first `}`, and
+// pgn has no escape for it, so a comment holding one is written as
+// comments to the end of the line instead, one for each of its lines
+pub(crate) fn push_comment(movetext: &mut Vec<String>, comment: &str) {
+    if comment.contains('}') {
+        for line in comment.lines() {
+            movetext.push(format!(";{}\n", line));
+        }
+    } else {
+        movetext.push(format!("{{{}}}", comment));
+    }
+}
+
+// join movetext tokens with spaces, wrapping lines at MAX_LINE_LENGTH.
+// tokens ending in a newline end their line
+pub(crate) fn wrap_movetext(pgn: &mut String, movetext: &[String]) {
+    let mut line_length = 0;
+    for token in movetext {
+        if line_length > 0 && line_length + 1 + token.len() > MAX_LINE_LENGTH {
+            pgn.push('\n');
+            line_length = 0;
+        } else if line_length > 0 {
+            pgn.push(' ');
+            line_length += 1;
+        }
+        pgn.push_str(token);
+        line_length = match token.ends_with('\n') {
+            true => 0,
+            false => line_length + token.len(),
+        };
+    }
+    pgn.push('\n');
+}
+
+impl Game {
+    // read the first game from pgn text
+    //
+    // tags are kept in `tags`, and the game is replayed from the SetUp/FEN
+    // position, or the standard one. comments and NAGs are attached to the
+    // move they follow, and those before the first move are kept in
+    // `comment` and `nags`; variations are skipped. games ending without
+    // checkmate or stalemate take their status from the result, e.g. "1-0"
+    // is read as `WhiteWins`.
+    pub fn from_pgn(pgn: &str) -> Result<Self, PgnError> {
+        let tokens = tokenize(pgn)?;
+        if tokens.is_empty() {
+            return Err(PgnError::Malformed(String::from("no game")));
+        }
+
+        let mut tags = vec![];
+        let mut movetext = &tokens[..];
+        while let Some((Token::Tag(name, value), rest)) = movetext.split_first() {
+            tags.push((name.to_string(), value.clone()));
+            movetext = rest;
+        }
+
+        let mut game = Game {
+            board: starting_board(&tags)?,
+            tags,
+            ..Default::default()
+        };
+
+        let mut result = None;
+        let mut variation_depth = 0;
+        for token in movetext {
+            match token {
+                Token::StartVariation => variation_depth += 1,
+                Token::EndVariation if variation_depth == 0 => {
+                    return Err(PgnError::Malformed(String::from("unopened variation")));
+                }
+                Token::EndVariation => variation_depth -= 1,
+                _ if variation_depth > 0 => {}
+                Token::Tag(_, _) => {
+                    return Err(PgnError::Malformed(String::from("tag in movetext")));
+                }
+                Token::MoveNumber => {}
+                Token::San(san) => {
+                    let chess_move = parse_san_move(&game.board, san)
+                        .map_err(|error| PgnError::InvalidMove(san.to_string(), error))?;
+                    if game.status.is_some() || game.play_move(chess_move).is_err() {
+                        return Err(PgnError::InvalidMove(san.to_string(), SanError::Illegal));
+                    }
+                }
+                Token::Nag(nag) => match game.history.last_mut() {
+                    Some(entry) => entry.nags.push(*nag),
+                    None => game.nags.push(*nag),
+                },
+                Token::Comment(comment) => {
+                    let previous = match game.history.last_mut() {
+                        Some(entry) => &mut entry.comment,
+                        None => &mut game.comment,
+                    };
+                    *previous = Some(match previous.take() {
+                        Some(previous) => previous + " " + comment,
+                        None => comment.to_string(),
+                    });
+                }
+                Token::Result(token) => {
+                    result = Some(*token);
+                    break;
+                }
+            }
+        }
+        if variation_depth > 0 {
+            return Err(PgnError::Malformed(String::from("unclosed variation")));
+        }
+
+        let result = result.or_else(|| game.get_tag("Result"));
+        if game.status.is_none() {
+            game.status = result.and_then(status_for_result);
+        }
+
+        Ok(game)
+    }
+
+    // write the game as pgn, including its tags, comments and NAGs
+    pub fn to_pgn(&self) -> String {
+        let result = result_token(&self.status);
+        let start = self.history.first().map_or(self.board, |entry| entry.board);
+
+        let mut pgn = String::new();
+        write_tags(&mut pgn, &self.tags, &start, result);
+        pgn.push('\n');
+
+        let mut movetext = vec![];
+        for nag in &self.nags {
+            movetext.push(format!("${}", nag));
+        }
+        if let Some(comment) = &self.comment {
+            push_comment(&mut movetext, comment);
+        }
+        let mut after_comment = true;
+        for entry in &self.history {
+            let number = entry.board.get_fullmove_number();
+            if entry.board.get_turn_color() == WHITE {
+                movetext.push(format!("{}.", number));
+            } else if after_comment {
+                movetext.push(format!("{}...", number));
+            }
+            movetext.push(entry.board.to_san(entry.chess_move));
+            for nag in &entry.nags {
+                movetext.push(format!("${}", nag));
+            }
+            after_comment = entry.comment.is_some();
+            if let Some(comment) = &entry.comment {
+                push_comment(&mut movetext, comment);
+            }
+        }
+        movetext.push(result.to_string());
+        wrap_movetext(&mut pgn, &movetext);
+
+        pgn
+    }
+}
+
+// reads games one at a time from the lines of a multi-game pgn file
+//
+// works with any source of lines, so a whole file never needs to be in
+// memory. with std, a file can be read with
+// `PgnReader::new(BufReader::new(file).lines().map_while(Result::ok))`
+pub struct PgnReader<I> {
+    lines: I,
+    // first line of the next game, read while finding the end of the last one
+    pending: Option<String>,
+}
+
+impl<I> PgnReader<I> {
+    pub fn new(lines: I) -> Self {
+        PgnReader {
+            lines,
+            pending: None,
+        }
+    }
+}
+
+impl<I, S> Iterator for PgnReader<I>
+where
+    I: Iterator<Item = S>,
+    S: AsRef<str>,
+{
+    type Item = Result<Game, PgnError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut text = String::new();
+        let mut in_movetext = false;
+        let mut in_comment = false;
+
+        loop {
+            let line = match self.pending.take() {
+                Some(line) => line,
+                None => match self.lines.next() {
+                    Some(line) => line.as_ref().to_string(),
+                    None => break,
+                },
+            };
+
+            let trimmed = line.trim();
+            let is_movetext = !in_comment
+                && !trimmed.is_empty()
+                && !trimmed.starts_with('[')
+                && !trimmed.starts_with('%');
+            if !in_comment && in_movetext && trimmed.starts_with('[') {
+                // tags of the next game
+                self.pending = Some(line);
+                break;
+            }
+            in_movetext |= is_movetext;
+
+            // strip comments to find the last token of the line
+            let mut code = String::new();
+            for c in line.chars() {
+                match c {
+                    '}' if in_comment => in_comment = false,
+                    _ if in_comment => {}
+                    '{' => in_comment = true,
+                    ';' => break,
+                    c => code.push(c),
+                }
+            }
+
+            text.push_str(&line);
+            text.push('\n');
+
+            let ends_game = matches!(
+                code.split_whitespace().last(),
+                Some("1-0" | "0-1" | "1/2-1/2" | "*")
+            );
+            if in_movetext && !in_comment && ends_game {
+                break;
+            }
+        }
+
+        if text.trim().is_empty() {
+            None
+        } else {
+            Some(Game::from_pgn(&text))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::position::*;
+    use crate::{Color, Piece};
+
+    const IMMORTAL_GAME: &str = r#"[Event "London"]
+[Site "London ENG"]
+[Date "1851.06.21"]
+[Round "?"]
+[White "Adolf Anderssen"]
+[Black "Lionel Kieseritzky"]
+[Result "1-0"]
+[ECO "C33"]
+
+1. e4 e5 2. f4 exf4 3. Bc4 Qh4+ 4. Kf1 b5 5. Bxb5 Nf6 6. Nf3 Qh6 7. d3 Nh5 8.
+Nh4 Qg5 9. Nf5 c6 10. g4 Nf6 11. Rg1 cxb5 12. h4 Qg6 13. h5 Qg5 14. Qf3 Ng8 15.
+Bxf4 Qf6 16. Nc3 Bc5 17. Nd5 Qxb2 18. Bd6 Bxg1 19. e5 Qxa1+ 20. Ke2 Na6 21.
+Nxg7+ Kd8 22. Qf6+ Nxf6 23. Be7# 1-0
+"#;
+
+    #[test]
+    fn test_pgn_round_trip() {
+        let game = Game::from_pgn(IMMORTAL_GAME).unwrap();
+        assert_eq!(game.status, Some(GameOver::WhiteCheckmates));
+        assert_eq!(game.get_tag("White"), Some("Adolf Anderssen"));
+        assert_eq!(game.get_tag("ECO"), Some("C33"));
+        assert_eq!(game.history.len(), 45);
+        assert_eq!(game.to_pgn(), IMMORTAL_GAME);
+    }
+
+    #[test]
+    fn test_pgn_annotations() {
+        let pgn = "[Event \"Casual \\\"blitz\\\"\"]\n\n\
+                   1. e4 {best by test} e5 $1 2. Nf3!? ; king's knight\n\
+                   Nc6 (2... d6 3. d4) 3. Bb5 a6?! 4. Ba4 *";
+        let game = Game::from_pgn(pgn).unwrap();
+        assert_eq!(game.status, None);
+        assert_eq!(game.get_tag("Event"), Some("Casual \"blitz\""));
+        assert_eq!(game.history.len(), 7);
+        assert_eq!(game.history[0].comment, Some(String::from("best by test")));
+        assert_eq!(game.history[1].nags, vec![1]);
+        assert_eq!(game.history[2].nags, vec![5]);
+        assert_eq!(game.history[2].comment, Some(String::from("king's knight")));
+        assert_eq!(game.history[5].nags, vec![6]);
+
+        assert_eq!(
+            game.to_pgn(),
+            "[Event \"Casual \\\"blitz\\\"\"]\n\
+             [Site \"?\"]\n\
+             [Date \"????.??.??\"]\n\
+             [Round \"?\"]\n\
+             [White \"?\"]\n\
+             [Black \"?\"]\n\
+             [Result \"*\"]\n\
+             \n\
+             1. e4 {best by test} 1... e5 $1 2. Nf3 $5 {king's knight} 2... Nc6 3. Bb5 a6 $6\n\
+             4. Ba4 *\n"
+        );
+        assert_eq!(
+            Game::from_pgn(&game.to_pgn()).unwrap().to_pgn(),
+            game.to_pgn()
+        );
+
+        // comments before the first move are about the whole game
+        let game = Game::from_pgn("{Opening notes} 1. {the king's pawn} e4 e5 *").unwrap();
+        assert_eq!(
+            game.comment,
+            Some(String::from("Opening notes the king's pawn"))
+        );
+        assert_eq!(game.history[0].comment, None);
+        let exported = game.to_pgn();
+        assert!(exported.ends_with(
+            "
+{Opening notes the king's pawn} 1. e4 e5 *
+"
+        ));
+        assert_eq!(Game::from_pgn(&exported).unwrap().comment, game.comment);
+
+        // so are NAGs, read the same way as by GameTree
+        let game = Game::from_pgn("$1 {Notes} 1. e4 *").unwrap();
+        assert_eq!(game.nags, vec![1]);
+        assert_eq!(game.history[0].nags, Vec::<u8>::new());
+        assert!(game.to_pgn().ends_with("\n$1 {Notes} 1. e4 *\n"));
+
+        // a comment can't be written between braces if it holds a brace
+        let mut game = Game::from_pgn("1. e4 e5 *").unwrap();
+        game.history[0].comment = Some(String::from("not 1... {e5}\nbut c5"));
+        let exported = game.to_pgn();
+        assert!(exported.ends_with("\n1. e4 ;not 1... {e5}\n;but c5\n1... e5 *\n"));
+        assert_eq!(
+            Game::from_pgn(&exported).unwrap().history[0].comment,
+            Some(String::from("not 1... {e5} but c5"))
+        );
+    }
+
+    #[test]
+    fn test_pgn_setup() {
+        let pgn = "[Result \"1/2-1/2\"]\n\
+                   [SetUp \"1\"]\n\
+                   [FEN \"4k3/8/4K3/4P3/8/8/8/8 b - - 12 60\"]\n\
+                   \n\
+                   60... Kf8 61. Kd7 1/2-1/2";
+        let game = Game::from_pgn(pgn).unwrap();
+        assert_eq!(game.status, Some(GameOver::Drawn));
+        assert_eq!(
+            game.board.get_piece(D7),
+            Some(Piece::King(Color::White, D7))
+        );
+        let exported = game.to_pgn();
+        assert!(exported.contains("[FEN \"4k3/8/4K3/4P3/8/8/8/8 b - - 12 60\"]\n"));
+        assert!(exported.ends_with("\n60... Kf8 61. Kd7 1/2-1/2\n"));
+        assert_eq!(Game::from_pgn(&exported).unwrap().board, game.board);
+    }
+
+    #[test]
+    fn test_pgn_errors() {
+        assert_eq!(
+            Game::from_pgn("1. e4 e5 2. Ke3").err(),
+            Some(PgnError::InvalidMove(
+                String::from("Ke3"),
+                SanError::Illegal
+            ))
+        );
+        assert_eq!(
+            Game::from_pgn("[FEN \"not a position\"]\n1. e4").err(),
+            Some(PgnError::InvalidPosition)
+        );
+        assert!(matches!(
+            Game::from_pgn("1. e4 {unterminated"),
+            Err(PgnError::Malformed(_))
+        ));
+        assert!(matches!(
+            Game::from_pgn("[Event \"?\"\n1. e4"),
+            Err(PgnError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn test_pgn_reader() {
+        let pgn = "[Event \"first\"]\n\n1. e4 e5 1-0\n\n\
+                   [Event \"second\"]\n\n1. d4 {a comment\nover [two] lines} d5 0-1\n\
+                   1. c4 *\n";
+        let games: Vec<Game> = PgnReader::new(pgn.lines()).map(Result::unwrap).collect();
+        assert_eq!(games.len(), 3);
+        assert_eq!(games[0].get_tag("Event"), Some("first"));
+        assert_eq!(games[0].status, Some(GameOver::WhiteWins));
+        assert_eq!(games[1].get_tag("Event"), Some("second"));
+        assert_eq!(games[1].history.len(), 2);
+        assert_eq!(games[2].history.len(), 1);
+        assert_eq!(games[2].status, None);
+    }
+}
@@ -1,6 +1,6 @@
 use crate::CoroutineIteratorAdapter;
 
-use super::{Board, Color, Move, Position};
+use super::{Board, Color, EvalParams, Move, Position};
 use alloc::string::String;
 use core::{convert::TryFrom, ops::Coroutine};
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
@@ -24,136 +24,22 @@ pub enum Piece {
     Pawn(Color, Position),
 }
 
-const WHITE_KING_POSITION_WEIGHTS: [[f64; 8]; 8] = [
-    [-3.0, -4.0, -4.0, -5.0, -5.0, -4.0, -4.0, -3.0],
-    [-3.0, -4.0, -4.0, -5.0, -5.0, -4.0, -4.0, -3.0],
-    [-3.0, -4.0, -4.0, -5.0, -5.0, -4.0, -4.0, -3.0],
-    [-3.0, -4.0, -4.0, -5.0, -5.0, -4.0, -4.0, -3.0],
-    [-2.0, -3.0, -3.0, -4.0, -4.0, -3.0, -3.0, -2.0],
-    [-1.0, -2.0, -2.0, -2.0, -2.0, -2.0, -2.0, -1.0],
-    [2.0, 2.0, 0.0, 0.0, 0.0, 0.0, 2.0, 2.0],
-    [2.0, 3.0, 1.0, 0.0, 0.0, 1.0, 3.0, 2.0],
-];
-
-const BLACK_KING_POSITION_WEIGHTS: [[f64; 8]; 8] = [
-    [2.0, 3.0, 1.0, 0.0, 0.0, 1.0, 3.0, 2.0],
-    [2.0, 2.0, 0.0, 0.0, 0.0, 0.0, 2.0, 2.0],
-    [-1.0, -2.0, -2.0, -2.0, -2.0, -2.0, -2.0, -1.0],
-    [-2.0, -3.0, -3.0, -4.0, -4.0, -3.0, -3.0, -2.0],
-    [-3.0, -4.0, -4.0, -5.0, -5.0, -4.0, -4.0, -3.0],
-    [-3.0, -4.0, -4.0, -5.0, -5.0, -4.0, -4.0, -3.0],
-    [-3.0, -4.0, -4.0, -5.0, -5.0, -4.0, -4.0, -3.0],
-    [-3.0, -4.0, -4.0, -5.0, -5.0, -4.0, -4.0, -3.0],
-];
-
-const WHITE_QUEEN_POSITION_WEIGHTS: [[f64; 8]; 8] = [
-    [-2.0, -1.0, -1.0, -0.5, -0.5, -1.0, -1.0, -2.0],
-    [-1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, -1.0],
-    [-1.0, 0.0, 0.5, 0.5, 0.5, 0.5, 0.0, -1.0],
-    [-0.5, 0.0, 0.5, 0.5, 0.5, 0.5, 0.0, -0.5],
-    [0.0, 0.0, 0.5, 0.5, 0.5, 0.5, 0.0, -0.5],
-    [-1.0, 0.5, 0.5, 0.5, 0.5, 0.5, 0.0, -1.0],
-    [-1.0, 0.0, 0.5, 0.0, 0.0, 0.0, 0.0, -1.0],
-    [-1.0, -0.0, -1.0, -0.5, -0.5, -0.5, -1.0, -2.0],
-];
-const BLACK_QUEEN_POSITION_WEIGHTS: [[f64; 8]; 8] = [
-    [-1.0, -0.0, -1.0, -0.5, -0.5, -0.5, -1.0, -2.0],
-    [-1.0, 0.0, 0.5, 0.0, 0.0, 0.0, 0.0, -1.0],
-    [-1.0, 0.5, 0.5, 0.5, 0.5, 0.5, 0.0, -1.0],
-    [0.0, 0.0, 0.5, 0.5, 0.5, 0.5, 0.0, -0.5],
-    [-0.5, 0.0, 0.5, 0.5, 0.5, 0.5, 0.0, -0.5],
-    [-1.0, 0.0, 0.5, 0.5, 0.5, 0.5, 0.0, -1.0],
-    [-1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, -1.0],
-    [-2.0, -1.0, -1.0, -0.5, -0.5, -1.0, -1.0, -2.0],
-];
-
-const WHITE_ROOK_POSITION_WEIGHTS: [[f64; 8]; 8] = [
-    [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
-    [0.5, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 0.5],
-    [-0.5, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, -0.5],
-    [-0.5, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, -0.5],
-    [-0.5, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, -0.5],
-    [-0.5, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, -0.5],
-    [-0.5, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, -0.5],
-    [0.0, 0.0, 0.0, 0.5, 0.5, 0.0, 0.0, 0.0],
-];
-
-const BLACK_ROOK_POSITION_WEIGHTS: [[f64; 8]; 8] = [
-    [0.0, 0.0, 0.0, 0.5, 0.5, 0.0, 0.0, 0.0],
-    [-0.5, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, -0.5],
-    [-0.5, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, -0.5],
-    [-0.5, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, -0.5],
-    [-0.5, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, -0.5],
-    [-0.5, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, -0.5],
-    [0.5, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 0.5],
-    [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
-];
-
-const WHITE_BISHOP_POSITION_WEIGHTS: [[f64; 8]; 8] = [
-    [-2.0, -1.0, -1.0, -1.0, -1.0, -1.0, -1.0, -2.0],
-    [-1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, -1.0],
-    [-1.0, 0.0, 0.5, 1.0, 1.0, 0.5, 0.0, -1.0],
-    [-1.0, 0.5, 0.5, 1.0, 1.0, 0.5, 0.5, -1.0],
-    [-1.0, 0.0, 1.0, 1.0, 1.0, 1.0, 0.0, -1.0],
-    [-1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, -1.0],
-    [-1.0, 0.5, 0.0, 0.0, 0.0, 0.0, 0.5, -1.0],
-    [-2.0, -1.0, -1.0, -1.0, -1.0, -1.0, -1.0, -2.0],
-];
-
-const BLACK_BISHOP_POSITION_WEIGHTS: [[f64; 8]; 8] = [
-    [-2.0, -1.0, -1.0, -1.0, -1.0, -1.0, -1.0, -2.0],
-    [-1.0, 0.5, 0.0, 0.0, 0.0, 0.0, 0.5, -1.0],
-    [-1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, -1.0],
-    [-1.0, 0.0, 1.0, 1.0, 1.0, 1.0, 0.0, -1.0],
-    [-1.0, 0.5, 0.5, 1.0, 1.0, 0.5, 0.5, -1.0],
-    [-1.0, 0.0, 0.5, 1.0, 1.0, 0.5, 0.0, -1.0],
-    [-1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, -1.0],
-    [-2.0, -1.0, -1.0, -1.0, -1.0, -1.0, -1.0, -2.0],
-];
-
-const WHITE_KNIGHT_POSITION_WEIGHTS: [[f64; 8]; 8] = [
-    [-5.0, -4.0, -3.0, -3.0, -3.0, -3.0, -4.0, -5.0],
-    [-4.0, -2.0, 0.0, 0.0, 0.0, 0.0, -2.0, -4.0],
-    [-3.0, 0.0, 1.0, 1.5, 1.5, 1.0, 0.0, -3.0],
-    [-3.0, 0.5, 1.5, 2.0, 2.0, 1.5, 0.5, -3.0],
-    [-3.0, 0.0, 1.5, 2.0, 2.0, 1.5, 0.0, -3.0],
-    [-3.0, 0.5, 1.0, 1.5, 1.5, 1.0, 0.5, -3.0],
-    [-4.0, -2.0, 0.0, 0.5, 0.5, 0.0, -2.0, -4.0],
-    [-5.0, -4.0, -3.0, -3.0, -3.0, -3.0, -4.0, -5.0],
-];
-
-const BLACK_KNIGHT_POSITION_WEIGHTS: [[f64; 8]; 8] = [
-    [-5.0, -4.0, -3.0, -3.0, -3.0, -3.0, -4.0, -5.0],
-    [-4.0, -2.0, 0.0, 0.5, 0.5, 0.0, -2.0, -4.0],
-    [-3.0, 0.5, 1.0, 1.5, 1.5, 1.0, 0.5, -3.0],
-    [-3.0, 0.0, 1.5, 2.0, 2.0, 1.5, 0.0, -3.0],
-    [-3.0, 0.5, 1.5, 2.0, 2.0, 1.5, 0.5, -3.0],
-    [-3.0, 0.0, 1.0, 1.5, 1.5, 1.0, 0.0, -3.0],
-    [-4.0, -2.0, 0.0, 0.0, 0.0, 0.0, -2.0, -4.0],
-    [-5.0, -4.0, -3.0, -3.0, -3.0, -3.0, -4.0, -5.0],
-];
-
-const WHITE_PAWN_POSITION_WEIGHTS: [[f64; 8]; 8] = [
-    [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
-    [5.0, 5.0, 5.0, 5.0, 5.0, 5.0, 5.0, 5.0],
-    [1.0, 1.0, 2.0, 3.0, 3.0, 2.0, 1.0, 1.0],
-    [0.5, 0.5, 1.0, 2.5, 2.5, 1.0, 0.5, 0.5],
-    [0.0, 0.0, 0.0, 2.0, 2.0, 0.0, 0.0, 0.0],
-    [0.5, -0.5, -1.0, 0.0, 0.0, -1.0, -0.5, 0.5],
-    [0.5, 1.5, -1.0, -2.0, -2.0, 1.0, 1.5, 0.5],
-    [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
-];
-
-const BLACK_PAWN_POSITION_WEIGHTS: [[f64; 8]; 8] = [
-    [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
-    [0.5, 1.5, -1.0, -2.0, -2.0, 1.0, 1.5, 0.5],
-    [0.5, -0.5, -1.0, 0.0, 0.0, -1.0, -0.5, 0.5],
-    [0.0, 0.0, 0.0, 2.0, 2.0, 0.0, 0.0, 0.0],
-    [0.5, 0.5, 1.0, 2.5, 2.5, 1.0, 0.5, 0.5],
-    [1.0, 1.0, 2.0, 3.0, 3.0, 2.0, 1.0, 1.0],
-    [5.0, 5.0, 5.0, 5.0, 5.0, 5.0, 5.0, 5.0],
-    [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
-];
+/// The value of a pawn in centipawns, the unit that boards are evaluated
+/// in. Every other value is an integer number of centipawns too, so that
+/// evaluation gives the same result on every platform.
+pub const PAWN_VALUE: i32 = 100;
+
+/// Convert a value in centipawns, like those from `Board::value_for`, to
+/// pawns for display.
+#[inline]
+pub fn centipawns_to_pawns(centipawns: i32) -> f64 {
+    centipawns as f64 / PAWN_VALUE as f64
+}
+
+/// The game phase with every knight, bishop, rook and queen on the board,
+/// with the default `EvalParams`. The phase of a board goes down to 0 as
+/// these pieces are traded.
+pub const MAX_PHASE: i32 = 24;
 
 impl core::fmt::Display for Piece {
     fn fmt(&self, f: &mut core::fmt::Formatter) -> Result<(), core::fmt::Error> {
@@ -215,6 +101,21 @@ impl Piece {
         }
     }
 
+    /// Get the letter used for the piece in algebraic notation, such as
+    /// `"N"` for a knight. Pawns are written without a letter, so this
+    /// returns `""` for them.
+    #[inline]
+    pub fn get_san_letter(&self) -> &'static str {
+        match self {
+            Self::King(_, _) => "K",
+            Self::Queen(_, _) => "Q",
+            Self::Rook(_, _) => "R",
+            Self::Bishop(_, _) => "B",
+            Self::Knight(_, _) => "N",
+            Self::Pawn(_, _) => "",
+        }
+    }
+
     /// Get the material value for a piece.
     /// | Name | Value |
     /// |-|-|
@@ -236,44 +137,57 @@ impl Piece {
         }
     }
 
-    /// Get the weighted value of a piece. This simply factors in position
-    /// to the pieces value. For example, a knight that is in the center is
-    /// more favorable than a knight on the side of the board. Similarly,
-    /// a king in the center of the board is highly unfavorable compared to
-    /// a king its respective side.
+    /// Get the weighted value of a piece in centipawns during the
+    /// middlegame. This simply factors in position to the pieces value. For
+    /// example, a knight that is in the center is more favorable than a
+    /// knight on the side of the board. Similarly, a king in the center of
+    /// the board is highly unfavorable compared to a king its respective
+    /// side.
     ///
-    /// Additionally, the weighted value of the piece is 10 times greater than
-    /// its material value, plus or minus a weight ranging between 5.0 and -5.0.
+    /// Additionally, the weighted value of the piece is its material value
+    /// times `PAWN_VALUE`, plus or minus a weight ranging between 50 and -50.
+    /// Kings can't be captured, so only their position is weighted.
+    ///
+    /// This uses the default `EvalParams`.
+    #[inline]
+    pub fn get_weighted_value(&self) -> i32 {
+        EvalParams::DEFAULT.get_middlegame_value(*self)
+    }
+
+    /// Like `get_weighted_value`, but for the endgame, where the king is
+    /// safest in the center and pawns are worth more the closer they are
+    /// to promoting.
+    #[inline]
+    pub fn get_endgame_value(&self) -> i32 {
+        EvalParams::DEFAULT.get_endgame_value(*self)
+    }
+
+    /// Get how much the piece adds to the game phase of a board, which is
+    /// used to blend the middlegame and endgame values of its pieces.
+    /// | Name | Phase |
+    /// |-|-|
+    /// | Queen | 4 |
+    /// | Rook | 2 |
+    /// | Bishop | 1 |
+    /// | Knight | 1 |
+    /// | King, Pawn | 0 |
     #[inline]
-    pub fn get_weighted_value(&self) -> f64 {
-        let weights = match self {
-            Self::King(c, _) => match c {
-                Color::White => WHITE_KING_POSITION_WEIGHTS,
-                Color::Black => BLACK_KING_POSITION_WEIGHTS,
-            },
-            Self::Queen(c, _) => match c {
-                Color::White => WHITE_QUEEN_POSITION_WEIGHTS,
-                Color::Black => BLACK_QUEEN_POSITION_WEIGHTS,
-            },
-            Self::Rook(c, _) => match c {
-                Color::White => WHITE_ROOK_POSITION_WEIGHTS,
-                Color::Black => BLACK_ROOK_POSITION_WEIGHTS,
-            },
-            Self::Bishop(c, _) => match c {
-                Color::White => WHITE_BISHOP_POSITION_WEIGHTS,
-                Color::Black => BLACK_BISHOP_POSITION_WEIGHTS,
-            },
-            Self::Knight(c, _) => match c {
-                Color::White => WHITE_KNIGHT_POSITION_WEIGHTS,
-                Color::Black => BLACK_KNIGHT_POSITION_WEIGHTS,
-            },
-            Self::Pawn(c, _) => match c {
-                Color::White => WHITE_PAWN_POSITION_WEIGHTS,
-                Color::Black => BLACK_PAWN_POSITION_WEIGHTS,
-            },
-        };
-        weights[(7 - self.get_pos().get_row()) as usize][self.get_pos().get_col() as usize]
-            + (self.get_material_value() * 10) as f64
+    pub fn get_phase_weight(&self) -> i32 {
+        EvalParams::DEFAULT.get_phase_weight(*self)
+    }
+
+    // the index of the kind of piece in tables of every kind, in the order
+    // king, queen, rook, bishop, knight, pawn
+    #[inline]
+    pub(crate) fn get_kind_index(&self) -> usize {
+        match self {
+            Self::King(_, _) => 0,
+            Self::Queen(_, _) => 1,
+            Self::Rook(_, _) => 2,
+            Self::Bishop(_, _) => 3,
+            Self::Knight(_, _) => 4,
+            Self::Pawn(_, _) => 5,
+        }
     }
 
     /// Get the color of a given piece.
@@ -426,6 +340,23 @@ impl Piece {
         })
     }
 
+    // like `get_legal_moves`, but only captures and promotions, which
+    // skips checking the legality of every quiet move
+    pub(crate) fn get_legal_captures(self, board: &Board) -> impl Iterator<Item = Move> + '_ {
+        let color = self.get_color();
+        let moves = CoroutineIteratorAdapter::new(self.get_moves(board));
+        moves.filter(move |x| match x {
+            Move::Piece(from, to) => {
+                from.is_on_board()
+                    && to.is_on_board()
+                    && board.get_captured_piece(*x).is_some()
+                    && board.is_legal_move(*x, color)
+            }
+            Move::Promotion(_, _, _) => board.is_legal_move(*x, color),
+            _ => false,
+        })
+    }
+
     pub(crate) fn get_moves(self, board: &Board) -> impl Coroutine<Yield = Move, Return = ()> + '_ {
         move || {
             match self {
@@ -449,16 +380,32 @@ impl Piece {
                         yield Move::Piece(pos, next_up)
                     }
 
-                    if up.is_on_board() && board.has_no_piece(up) {
-                        yield Move::Piece(pos, up)
-                    }
-
-                    if up_left.is_on_board() && board.has_enemy_piece(up_left, ally_color) {
-                        yield Move::Piece(pos, up_left)
-                    }
-
-                    if up_right.is_on_board() && board.has_enemy_piece(up_right, ally_color) {
-                        yield Move::Piece(pos, up_right)
+                    for (to, possible) in [
+                        (up, up.is_on_board() && board.has_no_piece(up)),
+                        (
+                            up_left,
+                            up_left.is_on_board() && board.has_enemy_piece(up_left, ally_color),
+                        ),
+                        (
+                            up_right,
+                            up_right.is_on_board() && board.has_enemy_piece(up_right, ally_color),
+                        ),
+                    ] {
+                        if !possible {
+                            continue;
+                        }
+                        if to.get_row() == 0 || to.get_row() == 7 {
+                            for promotion in [
+                                Self::Queen(ally_color, to),
+                                Self::Rook(ally_color, to),
+                                Self::Bishop(ally_color, to),
+                                Self::Knight(ally_color, to),
+                            ] {
+                                yield Move::Promotion(pos, to, promotion)
+                            }
+                        } else {
+                            yield Move::Piece(pos, to)
+                        }
                     }
                 }
 
@@ -479,7 +426,8 @@ impl Piece {
                     }
                     if board.can_kingside_castle(ally_color) {
                         yield Move::KingSideCastle;
-                    } else if board.can_queenside_castle(ally_color) {
+                    }
+                    if board.can_queenside_castle(ally_color) {
                         yield Move::QueenSideCastle;
                     }
                 }
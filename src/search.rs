@@ -0,0 +1,1191 @@
+use super::*;
+use crate::pawns::{PawnTable, PAWN_TABLE_SIZE};
+use core::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+use either::Either;
+use rand::seq::IteratorRandom;
+use rand_chacha::ChaCha20Rng;
+
+/// The value of checkmating on the current board, in centipawns like
+/// `Board::value_for`. A mate `n` moves ahead is worth `MATE - n`, so
+/// that sooner mates are preferred.
+///
+/// This is far more than any board is worth, so that evaluations are
+/// never mistaken for mates.
+pub(crate) const MATE: i32 = 100_000_000;
+
+/// Values further from zero than this are mates.
+const MATE_BOUND: i32 = MATE - 1000;
+
+/// A value beyond any score, which can be negated without overflowing.
+pub(crate) const INFINITY: i32 = i32::MAX;
+
+/// How often, in boards evaluated, a search checks its clock and stop flag.
+const CHECK_INTERVAL: u64 = 1024;
+
+/// Ordering scores for moves, which are searched highest first.
+const CAPTURE_SCORE: u32 = 1 << 30;
+const KILLER_SCORE: u32 = 1 << 29;
+
+/// How much a capture's ordering score goes down for a more valuable
+/// attacker, compared to a more valuable victim.
+const ATTACKER_WEIGHT: i32 = 100;
+
+/// How much more than the material it wins a capture might change the
+/// score by, used to skip hopeless captures in the quiescence search.
+const DELTA_MARGIN: i32 = 200;
+
+/// When a search started with `Searcher::search` ends. A search stops at
+/// whichever limit is reached first, and searches until it is stopped
+/// when no limit is set.
+///
+/// `movetime` and `deadline` are measured with the clock given to
+/// `Searcher::set_clock`, and are ignored by searches without one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SearchLimits {
+    /// The deepest iteration, in moves of lookahead for either player.
+    /// A depth of 1 only looks at the current player's own moves.
+    pub depth: Option<u8>,
+    /// The number of boards to evaluate.
+    pub nodes: Option<u64>,
+    /// The time to search for, from when the search starts.
+    pub movetime: Option<Duration>,
+    /// The time on the search's clock to stop at.
+    pub deadline: Option<Duration>,
+}
+
+/// The score of a position for the player whose turn it is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Score {
+    /// The advantage in hundredths of a pawn.
+    Centipawns(i32),
+    /// The player can checkmate in this many of their own moves, or is
+    /// checkmated in this many moves when it's negative.
+    Mate(i32),
+}
+
+impl Score {
+    // a number that orders scores from worst to best
+    fn rank(&self) -> i64 {
+        match *self {
+            Self::Centipawns(centipawns) => centipawns as i64,
+            // mating sooner and being mated later are better
+            Self::Mate(moves) if moves > 0 => i64::MAX - moves as i64,
+            Self::Mate(moves) => i64::MIN - moves as i64,
+        }
+    }
+}
+
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Score {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        self.rank().cmp(&other.rank())
+    }
+}
+
+impl core::fmt::Display for Score {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> Result<(), core::fmt::Error> {
+        match self {
+            Self::Centipawns(centipawns) => write!(f, "{:+.2}", centipawns_to_pawns(*centipawns)),
+            Self::Mate(moves) => write!(f, "#{}", moves),
+        }
+    }
+}
+
+/// The outcome of a search.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchResult {
+    /// The best move, which is `Move::Resign` when there are no legal moves.
+    pub best_move: Move,
+    /// The line of play expected to follow, starting with the best move.
+    pub pv: Vec<Move>,
+    /// The number of moves of lookahead of the deepest completed iteration.
+    pub depth: u8,
+    /// The most moves ahead the search looked, following captures.
+    pub seldepth: u8,
+    /// The number of boards evaluated.
+    pub nodes: u64,
+    pub score: Score,
+    /// The time spent searching, measured with the search's clock.
+    pub elapsed: Duration,
+}
+
+/// Searches positions for the best move, keeping what it learns in a
+/// transposition table between searches.
+///
+/// `search` uses iterative deepening: it searches one move of lookahead,
+/// then two, and so on, until a `SearchLimits` is reached or the search is
+/// cancelled through its stop flag. It then returns the best move from the
+/// deepest iteration that completed. The first iteration always completes,
+/// so a move is found even with very tight limits.
+///
+/// ```rust
+/// use chess_engine::{Board, SearchLimits, Searcher};
+///
+/// let result = Searcher::default().search(
+///     &Board::default(),
+///     SearchLimits { nodes: Some(5000), ..SearchLimits::default() },
+///     |result| println!("depth {}: {} ({})", result.depth, result.best_move, result.score),
+/// );
+/// assert!(result.nodes <= 5000);
+/// assert_eq!(result.pv[0], result.best_move);
+/// ```
+pub struct Searcher<'a> {
+    table: TranspositionTable<'a>,
+    history: Vec<Board>,
+    clock: Option<&'a dyn Fn() -> Duration>,
+    stop: Option<&'a AtomicBool>,
+    limits: SearchLimits,
+    // clock time when the current search started
+    started: Duration,
+    board_count: u64,
+    // moves from the root to the board being searched
+    ply: usize,
+    // the deepest ply visited in the current search
+    seldepth: usize,
+    // the best line found from the board at each ply
+    pv: Vec<Vec<Move>>,
+    // quiet moves that caused a cutoff, two for each ply
+    killers: Vec<[Option<Move>; 2]>,
+    // how often quiet moves caused a cutoff, by from and to square
+    history_table: [[u32; 64]; 64],
+    params: &'a EvalParams,
+    // the pawn structures of evaluated boards
+    pawn_table: PawnTable,
+    // whether the current iteration may be cut short
+    can_abort: bool,
+    aborted: bool,
+}
+
+impl Default for Searcher<'static> {
+    fn default() -> Self {
+        Self::new(TranspositionTable::default())
+    }
+}
+
+impl<'a> Searcher<'a> {
+    /// Create a searcher that remembers positions in `table`. A searcher
+    /// with an empty table doesn't cache pawn structures either, so it
+    /// allocates nothing up front.
+    pub fn new(table: TranspositionTable<'a>) -> Self {
+        let pawn_table_size = if table.capacity() == 0 {
+            0
+        } else {
+            PAWN_TABLE_SIZE
+        };
+        Self {
+            table,
+            history: Vec::new(),
+            clock: None,
+            stop: None,
+            limits: SearchLimits::default(),
+            started: Duration::ZERO,
+            board_count: 0,
+            ply: 0,
+            seldepth: 0,
+            pv: Vec::new(),
+            killers: Vec::new(),
+            history_table: [[0; 64]; 64],
+            params: &EvalParams::DEFAULT,
+            pawn_table: PawnTable::new(pawn_table_size),
+            can_abort: false,
+            aborted: false,
+        }
+    }
+
+    /// Set the positions played before the ones that will be searched.
+    /// Repeating any of them is scored as a draw.
+    pub fn set_history(mut self, history: &[Board]) -> Self {
+        self.history = history.to_vec();
+        self
+    }
+
+    /// Set the clock that `movetime` and `deadline` limits are measured
+    /// with, which returns the time since any fixed point.
+    pub fn set_clock(mut self, clock: &'a dyn Fn() -> Duration) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    /// Set a flag that cancels the search, from another thread or an
+    /// interrupt, when it is set to `true`.
+    pub fn set_stop(mut self, stop: &'a AtomicBool) -> Self {
+        self.stop = Some(stop);
+        self
+    }
+
+    /// Set the weights that boards are evaluated with, instead of the
+    /// default `EvalParams`.
+    pub fn set_params(mut self, params: &'a EvalParams) -> Self {
+        self.params = params;
+        // the cached pawn structures were scored with the old weights
+        self.pawn_table.clear();
+        self
+    }
+
+    pub fn get_table(&self) -> &TranspositionTable<'a> {
+        &self.table
+    }
+
+    pub fn into_table(self) -> TranspositionTable<'a> {
+        self.table
+    }
+
+    /// Search `board` with iterative deepening until `limits` are reached,
+    /// calling `on_iteration` with the result of every completed iteration.
+    ///
+    /// This returns the result of the deepest completed iteration, with
+    /// the number of boards evaluated by every iteration.
+    pub fn search(
+        &mut self,
+        board: &Board,
+        limits: SearchLimits,
+        mut on_iteration: impl FnMut(&SearchResult),
+    ) -> SearchResult {
+        self.search_multipv(board, limits, 1, |lines| on_iteration(&lines[0]))
+            .remove(0)
+    }
+
+    /// Like `search`, but finding the best `n` moves, each with its own
+    /// line of play and score. The lines are ranked best first.
+    ///
+    /// There are fewer than `n` lines when there are fewer legal moves,
+    /// but always at least one.
+    pub fn search_multipv(
+        &mut self,
+        board: &Board,
+        limits: SearchLimits,
+        n: usize,
+        mut on_iteration: impl FnMut(&[SearchResult]),
+    ) -> Vec<SearchResult> {
+        self.limits = limits;
+        self.started = self.now();
+        self.board_count = 0;
+        self.seldepth = 0;
+        self.aborted = false;
+        self.killers.clear();
+        self.history_table = [[0; 64]; 64];
+
+        let legal_moves = board.get_legal_moves().count();
+        let mut best = Vec::new();
+        for depth in 1..=limits.depth.unwrap_or(u8::MAX).max(1) {
+            self.can_abort = depth > 1;
+            if self.can_abort && self.should_stop() {
+                break;
+            }
+
+            // each line is the best move that isn't in a line already
+            let mut lines: Vec<SearchResult> = Vec::new();
+            let mut excluded = Vec::new();
+            while lines.is_empty() || (lines.len() < n && excluded.len() < legal_moves) {
+                let (best_move, _, value) = match self.search_root(board, depth - 1, &excluded) {
+                    Some(result) => result,
+                    None => break,
+                };
+                let pv = self.complete_pv(board, self.pv[0].clone(), depth);
+                excluded.push(best_move);
+                lines.push(SearchResult {
+                    best_move,
+                    score: Score::from_value(value),
+                    pv,
+                    depth,
+                    seldepth: self.seldepth.min(u8::MAX as usize) as u8,
+                    nodes: self.board_count,
+                    elapsed: self.now().saturating_sub(self.started),
+                });
+            }
+            if self.aborted {
+                break;
+            }
+
+            best = lines;
+            on_iteration(&best);
+        }
+
+        let elapsed = self.now().saturating_sub(self.started);
+        for line in &mut best {
+            line.nodes = self.board_count;
+            line.elapsed = elapsed;
+        }
+        best
+    }
+
+    /// Search every move from `board` except the `excluded` ones, with
+    /// `depth` moves of lookahead after it, returning `None` if the search
+    /// was cut short.
+    ///
+    /// Moves are searched best first, so later moves only have to be
+    /// shown to be no better than the best so far. Of moves with equal
+    /// scores, the first one searched is chosen.
+    pub(crate) fn search_root(
+        &mut self,
+        board: &Board,
+        depth: u8,
+        excluded: &[Move],
+    ) -> Option<(Move, u64, i32)> {
+        let board_count = self.board_count;
+        let mut best_move_value = -INFINITY;
+        let mut best_move = Move::Resign;
+
+        let color = board.get_current_player_color();
+        let hash_move = self.table.probe(board).map(|entry| entry.get_best_move());
+        let moves = self.order_moves(
+            board,
+            board
+                .get_legal_moves()
+                .filter(|m| !excluded.contains(m))
+                .collect(),
+            hash_move,
+        );
+
+        self.clear_pv();
+        self.history.push(*board);
+        self.ply += 1;
+        for m in moves {
+            let child_board_value = self.minimax(
+                &board.apply_eval_move(m),
+                Either::Left(depth),
+                best_move_value,
+                INFINITY,
+                false,
+                color,
+            );
+            if self.aborted {
+                break;
+            }
+            if best_move == Move::Resign || child_board_value > best_move_value {
+                best_move = m;
+                best_move_value = child_board_value;
+                self.update_pv(m);
+            }
+        }
+        self.ply -= 1;
+        self.history.pop();
+
+        if self.aborted {
+            return None;
+        }
+        if best_move == Move::Resign {
+            best_move_value = mate_or_stalemate(board, 0);
+        }
+
+        // every other move was shown to be no better, so the score is exact,
+        // unless better moves were left out
+        if excluded.is_empty() {
+            self.table.store(
+                board,
+                depth.saturating_add(1),
+                Bound::Exact,
+                best_move_value,
+                best_move,
+            );
+        }
+
+        Some((best_move, self.board_count - board_count, best_move_value))
+    }
+
+    /// Perform minimax on `board`, scoring any position that repeats one
+    /// in the history as a draw.
+    ///
+    /// The history is extended with each position while its replies are
+    /// searched, and is restored before returning.
+    ///
+    /// Positions searched to a fixed depth are looked up in and stored to
+    /// the table. Scores in the table are kept for the player whose turn it
+    /// is, so that they don't depend on who the search is for.
+    pub(crate) fn minimax(
+        &mut self,
+        board: &Board,
+        depth: Either<u8, (&[u8], ChaCha20Rng)>,
+        mut alpha: i32,
+        mut beta: i32,
+        is_maximizing: bool,
+        getting_move_for: Color,
+    ) -> i32 {
+        if !self.visit() {
+            // the result is thrown away
+            return 0;
+        }
+        self.clear_pv();
+
+        if self
+            .history
+            .iter()
+            .any(|previous| previous.is_same_position(board))
+        {
+            // draw by repetition
+            return 0;
+        }
+
+        let (mut next_depth, max_moves) = match depth {
+            Either::Left(0) => {
+                return self.quiescence(board, alpha, beta, is_maximizing, getting_move_for);
+            }
+            Either::Right((depth, _)) if depth.is_empty() => {
+                return self.quiescence(board, alpha, beta, is_maximizing, getting_move_for);
+            }
+            Either::Left(depth) => (Either::Left(depth - 1), None),
+            Either::Right((depth, rng)) => (Either::Right((&depth[1..], rng)), Some(depth[0])),
+        };
+
+        // the table holds scores for the player to move, and the window
+        // is flipped to match when it's the other player's turn
+        let sign = if is_maximizing { 1 } else { -1 };
+        let (original_alpha, original_beta) = (alpha, beta);
+        let mut hash_move = None;
+        if let Either::Left(depth) = next_depth {
+            if let Some(entry) = self.table.probe(board) {
+                hash_move = Some(entry.get_best_move());
+                if entry.get_depth() > depth {
+                    let score = score_from_table(entry.get_score(), self.ply) * sign;
+                    match (entry.get_bound(), is_maximizing) {
+                        (Bound::Exact, _) => return score,
+                        (Bound::Lower, true) | (Bound::Upper, false) if score >= beta => {
+                            return score
+                        }
+                        (Bound::Lower, true) | (Bound::Upper, false) => alpha = alpha.max(score),
+                        (Bound::Upper, true) | (Bound::Lower, false) if score <= alpha => {
+                            return score
+                        }
+                        (Bound::Upper, true) | (Bound::Lower, false) => beta = beta.min(score),
+                    }
+                }
+            }
+        }
+
+        // a limit of 0 tries every move
+        let moves: Vec<Move> = match (&mut next_depth, max_moves) {
+            (Either::Right((_, rng)), Some(max_moves)) if max_moves > 0 => board
+                .get_legal_moves()
+                .choose_multiple(rng, max_moves as usize),
+            _ => self.order_moves(board, board.get_legal_moves().collect(), hash_move),
+        };
+
+        if moves.is_empty() {
+            let value = mate_or_stalemate(board, self.ply);
+            return if is_maximizing { value } else { -value };
+        }
+
+        let mut best_move_value;
+        let mut best_move = Move::Resign;
+        self.history.push(*board);
+        self.ply += 1;
+
+        if is_maximizing {
+            best_move_value = -INFINITY;
+
+            for m in moves {
+                let child_board_value = self.minimax(
+                    &board.apply_eval_move(m),
+                    next_depth.clone(),
+                    alpha,
+                    beta,
+                    !is_maximizing,
+                    getting_move_for,
+                );
+
+                if child_board_value > best_move_value {
+                    best_move_value = child_board_value;
+                    best_move = m;
+                    self.update_pv(m);
+                }
+
+                if best_move_value > alpha {
+                    alpha = best_move_value
+                }
+
+                if beta <= alpha {
+                    if let Either::Left(depth) = next_depth {
+                        // the ply was raised for the children of `board`
+                        self.record_cutoff(board, m, depth + 1, self.ply - 1);
+                    }
+                    break;
+                }
+            }
+        } else {
+            best_move_value = INFINITY;
+
+            for m in moves {
+                let child_board_value = self.minimax(
+                    &board.apply_eval_move(m),
+                    next_depth.clone(),
+                    alpha,
+                    beta,
+                    !is_maximizing,
+                    getting_move_for,
+                );
+                if child_board_value < best_move_value {
+                    best_move_value = child_board_value;
+                    best_move = m;
+                    self.update_pv(m);
+                }
+
+                if best_move_value < beta {
+                    beta = best_move_value
+                }
+
+                if beta <= alpha {
+                    if let Either::Left(depth) = next_depth {
+                        // the ply was raised for the children of `board`
+                        self.record_cutoff(board, m, depth + 1, self.ply - 1);
+                    }
+                    break;
+                }
+            }
+        }
+
+        self.ply -= 1;
+        self.history.pop();
+
+        if self.aborted {
+            return 0;
+        }
+
+        if let Either::Left(depth) = next_depth {
+            let bound = if best_move_value >= original_beta {
+                if is_maximizing {
+                    Bound::Lower
+                } else {
+                    Bound::Upper
+                }
+            } else if best_move_value <= original_alpha {
+                if is_maximizing {
+                    Bound::Upper
+                } else {
+                    Bound::Lower
+                }
+            } else {
+                Bound::Exact
+            };
+            self.table.store(
+                board,
+                depth + 1,
+                bound,
+                score_to_table(best_move_value * sign, self.ply),
+                best_move,
+            );
+        }
+
+        best_move_value
+    }
+
+    /// Keep searching captures and queen promotions from `board` until the
+    /// position is quiet, so that it isn't scored in the middle of an
+    /// exchange.
+    ///
+    /// The player to move may also "stand pat" and keep the score of
+    /// the board as it is, since they don't have to capture.
+    fn quiescence(
+        &mut self,
+        board: &Board,
+        mut alpha: i32,
+        mut beta: i32,
+        is_maximizing: bool,
+        getting_move_for: Color,
+    ) -> i32 {
+        let mut best_move_value =
+            board.evaluate(self.params, getting_move_for, Some(&mut self.pawn_table));
+        if is_maximizing {
+            if best_move_value >= beta {
+                return best_move_value;
+            }
+            alpha = alpha.max(best_move_value);
+        } else {
+            if best_move_value <= alpha {
+                return best_move_value;
+            }
+            beta = beta.min(best_move_value);
+        }
+
+        let mut captures: Vec<(Move, i32)> = board
+            .get_legal_captures()
+            .filter_map(|m| Some((m, capture_order(board, m)?)))
+            .collect();
+        captures.sort_by(|a, b| b.1.cmp(&a.1));
+
+        for (m, order) in captures {
+            // skip captures that can't change the outcome, even with a
+            // positional bonus on top of the material
+            let gain = (order + ATTACKER_WEIGHT - 1) / ATTACKER_WEIGHT * PAWN_VALUE + DELTA_MARGIN;
+            if (is_maximizing && best_move_value + gain <= alpha)
+                || (!is_maximizing && best_move_value - gain >= beta)
+            {
+                break;
+            }
+            if !self.visit() {
+                return 0;
+            }
+            self.ply += 1;
+            let child_board_value = self.quiescence(
+                &board.apply_eval_move(m),
+                alpha,
+                beta,
+                !is_maximizing,
+                getting_move_for,
+            );
+            self.ply -= 1;
+
+            if is_maximizing {
+                best_move_value = best_move_value.max(child_board_value);
+                alpha = alpha.max(best_move_value);
+            } else {
+                best_move_value = best_move_value.min(child_board_value);
+                beta = beta.min(best_move_value);
+            }
+            if beta <= alpha {
+                break;
+            }
+        }
+
+        best_move_value
+    }
+
+    /// Sort `moves` so that the ones most likely to be best are searched
+    /// first: the best move from an earlier search of `board`, then
+    /// captures and promotions, then quiet moves that caused cutoffs
+    /// elsewhere in the search.
+    fn order_moves(
+        &self,
+        board: &Board,
+        mut moves: Vec<Move>,
+        hash_move: Option<Move>,
+    ) -> Vec<Move> {
+        let killers = self.killers.get(self.ply).copied().unwrap_or_default();
+        moves.sort_by_cached_key(|&m| {
+            let score = if Some(m) == hash_move {
+                u32::MAX
+            } else if let Some(order) = capture_order(board, m) {
+                CAPTURE_SCORE + order.max(0) as u32
+            } else if Some(m) == killers[0] {
+                KILLER_SCORE + 1
+            } else if Some(m) == killers[1] {
+                KILLER_SCORE
+            } else {
+                match m {
+                    Move::Piece(from, to) | Move::Promotion(from, to, _) => self.history_table
+                        [square_index(from)][square_index(to)]
+                    .min(KILLER_SCORE - 1),
+                    _ => 0,
+                }
+            };
+            core::cmp::Reverse(score)
+        });
+        moves
+    }
+
+    /// Remember that the quiet move `m` from `board`, which was searched
+    /// at `ply`, caused a cutoff with `depth` moves of lookahead left, so
+    /// it's tried earlier elsewhere.
+    fn record_cutoff(&mut self, board: &Board, m: Move, depth: u8, ply: usize) {
+        if capture_order(board, m).is_some() {
+            return;
+        }
+
+        if self.killers.len() <= ply {
+            self.killers.resize(ply + 1, [None; 2]);
+        }
+        let killers = &mut self.killers[ply];
+        if killers[0] != Some(m) {
+            killers[1] = killers[0];
+            killers[0] = Some(m);
+        }
+
+        if let Move::Piece(from, to) = m {
+            let score = &mut self.history_table[square_index(from)][square_index(to)];
+            *score = score.saturating_add(depth as u32 * depth as u32);
+        }
+    }
+
+    // a line is cut short where the search used a score from the table,
+    // so follow the best moves stored in the table up to `depth` moves
+    fn complete_pv(&self, board: &Board, mut pv: Vec<Move>, depth: u8) -> Vec<Move> {
+        let mut position = *board;
+        for &m in &pv {
+            position = position.apply_eval_move(m);
+        }
+        while pv.len() < depth as usize {
+            let m = match self.table.probe(&position) {
+                Some(entry) => entry.get_best_move(),
+                None => break,
+            };
+            if !position.get_legal_moves().any(|legal| legal == m) {
+                break;
+            }
+            pv.push(m);
+            position = position.apply_eval_move(m);
+        }
+        pv
+    }
+
+    // forget the line from the board at the current ply
+    fn clear_pv(&mut self) {
+        if self.pv.len() <= self.ply + 1 {
+            self.pv.resize(self.ply + 2, Vec::new());
+        }
+        self.pv[self.ply].clear();
+    }
+
+    // while the replies to a board are searched, make `m` followed by the
+    // line found after it the line from that board
+    fn update_pv(&mut self, m: Move) {
+        let (lines, rest) = self.pv.split_at_mut(self.ply);
+        let line = &mut lines[self.ply - 1];
+        line.clear();
+        line.push(m);
+        line.extend_from_slice(&rest[0]);
+    }
+
+    pub(crate) fn get_board_count(&self) -> u64 {
+        self.board_count
+    }
+
+    // count a board as evaluated, returning false once the search is over
+    fn visit(&mut self) -> bool {
+        if self.aborted {
+            return false;
+        }
+        self.board_count += 1;
+        self.seldepth = self.seldepth.max(self.ply);
+
+        if self.can_abort {
+            let out_of_boards = self
+                .limits
+                .nodes
+                .map_or(false, |nodes| self.board_count >= nodes);
+            if out_of_boards || (self.board_count % CHECK_INTERVAL == 0 && self.should_stop()) {
+                self.aborted = true;
+                return false;
+            }
+        }
+        true
+    }
+
+    #[inline]
+    fn now(&self) -> Duration {
+        self.clock.map_or(Duration::ZERO, |clock| clock())
+    }
+
+    // whether a limit was reached or the search was cancelled
+    fn should_stop(&self) -> bool {
+        let now = self.now();
+        self.limits
+            .nodes
+            .map_or(false, |nodes| self.board_count >= nodes)
+            || self.stop.map_or(false, |stop| stop.load(Ordering::Relaxed))
+            || self.clock.is_some()
+                && (self
+                    .limits
+                    .movetime
+                    .map_or(false, |movetime| now >= self.started + movetime)
+                    || self
+                        .limits
+                        .deadline
+                        .map_or(false, |deadline| now >= deadline))
+    }
+}
+
+impl Score {
+    /// Convert the value of a position, as rated by `Board::value_for`, to
+    /// a score.
+    pub(crate) fn from_value(value: i32) -> Self {
+        if value.abs() > MATE_BOUND {
+            // count the moves of the player who mates
+            let moves = (MATE - value.abs() + 1) / 2;
+            Self::Mate(if value > 0 { moves } else { -moves })
+        } else {
+            Self::Centipawns(value)
+        }
+    }
+}
+
+/// The value of `board` for the player to move when they have no legal
+/// moves, `ply` moves into the search.
+fn mate_or_stalemate(board: &Board, ply: usize) -> i32 {
+    if board.is_in_check(board.get_turn_color()) {
+        -(MATE - ply as i32)
+    } else {
+        0
+    }
+}
+
+/// Mates are stored in the table counted from the board they were found
+/// for, rather than from the root of the search that found them.
+fn score_to_table(score: i32, ply: usize) -> i32 {
+    if score > MATE_BOUND {
+        score + ply as i32
+    } else if score < -MATE_BOUND {
+        score - ply as i32
+    } else {
+        score
+    }
+}
+
+fn score_from_table(score: i32, ply: usize) -> i32 {
+    if score > MATE_BOUND {
+        score - ply as i32
+    } else if score < -MATE_BOUND {
+        score + ply as i32
+    } else {
+        score
+    }
+}
+
+/// Find a checkmate for the player to move in exactly `moves` of their own
+/// moves, or fewer, returning the line with the longest defence.
+pub(crate) fn mate_in(board: &Board, moves: u8) -> Option<Vec<Move>> {
+    // moves that give check are most likely to mate, and are the only
+    // ones that can on the last move
+    let mut candidates: Vec<(Move, Board, bool)> = board
+        .get_legal_moves()
+        .map(|m| {
+            let next = board.apply_eval_move(m);
+            let check = next.is_in_check(next.get_turn_color());
+            (m, next, check)
+        })
+        .filter(|(_, _, check)| *check || moves > 1)
+        .collect();
+    candidates.sort_by_key(|(_, _, check)| !check);
+
+    'candidates: for (m, next, _) in candidates {
+        if next.is_checkmate() {
+            return Some(vec![m]);
+        }
+        if moves == 1 {
+            continue;
+        }
+
+        // every reply has to lose
+        let mut longest: Option<Vec<Move>> = None;
+        for reply in next.get_legal_moves() {
+            match mate_in(&next.apply_eval_move(reply), moves - 1) {
+                Some(line) => {
+                    if longest
+                        .as_ref()
+                        .map_or(true, |longest| line.len() >= longest.len())
+                    {
+                        let mut defence = vec![reply];
+                        defence.extend(line);
+                        longest = Some(defence);
+                    }
+                }
+                None => continue 'candidates,
+            }
+        }
+
+        // no replies and no checkmate is a stalemate
+        if let Some(defence) = longest {
+            let mut line = vec![m];
+            line.extend(defence);
+            return Some(line);
+        }
+    }
+    None
+}
+
+/// Order captures and queen promotions by the most valuable victim, and
+/// then by the least valuable attacker. Other moves have no order.
+fn capture_order(board: &Board, m: Move) -> Option<i32> {
+    let (from, promotion) = match m {
+        Move::Piece(from, _) => (from, 0),
+        Move::Promotion(from, _, piece) if piece.is_queen() => {
+            (from, piece.get_material_value() - 1)
+        }
+        _ => return None,
+    };
+    let victim = match board.get_captured_piece(m) {
+        Some(captured) => captured.get_material_value(),
+        None if promotion > 0 => 0,
+        None => return None,
+    };
+    let attacker = board.get_piece(from)?.get_material_value();
+    Some((victim + promotion) * ATTACKER_WEIGHT - attacker.min(ATTACKER_WEIGHT - 1))
+}
+
+#[inline]
+fn square_index(pos: Position) -> usize {
+    (pos.get_row() * 8 + pos.get_col()) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+
+    #[test]
+    fn test_search_limits() {
+        let board = Board::default();
+
+        let mut depths = vec![];
+        let result = Searcher::default().search(
+            &board,
+            SearchLimits {
+                depth: Some(3),
+                ..SearchLimits::default()
+            },
+            |result| depths.push(result.depth),
+        );
+        assert_eq!(depths, [1, 2, 3]);
+        let (expected_move, _, expected_value) = board.get_best_next_move(2);
+        assert_eq!(result.best_move, expected_move);
+        assert_eq!(result.score, Score::from_value(expected_value));
+
+        let result = Searcher::default().search(
+            &board,
+            SearchLimits {
+                nodes: Some(1000),
+                ..SearchLimits::default()
+            },
+            |_| {},
+        );
+        assert!(result.nodes <= 1000);
+
+        // every call to the clock takes a millisecond
+        let millis = Cell::new(0);
+        let clock = || {
+            millis.set(millis.get() + 1);
+            Duration::from_millis(millis.get())
+        };
+        let mut searcher = Searcher::default().set_clock(&clock);
+        let mut last_depth = 0;
+        searcher.search(
+            &board,
+            SearchLimits {
+                movetime: Some(Duration::from_millis(5)),
+                ..SearchLimits::default()
+            },
+            |result| last_depth = result.depth,
+        );
+        assert!(last_depth >= 1 && millis.get() < 10);
+    }
+
+    #[test]
+    fn test_quiescence() {
+        // the pawn on d5 is defended, so taking it loses the queen
+        let board = parse_fen("4k3/8/2p5/3p4/8/8/8/3QK3 w - - 0 1").unwrap();
+        let hanging = Move::Piece(D1, D5);
+        assert_ne!(board.get_best_next_move(0).0, hanging);
+        assert_ne!(board.get_next_move(&[255], [0; 32]).0, hanging);
+
+        // but taking an undefended pawn is fine
+        let board = parse_fen("4k3/8/8/3p4/8/8/8/3QK3 w - - 0 1").unwrap();
+        assert_eq!(board.get_best_next_move(0).0, hanging);
+        assert_eq!(board.get_next_move(&[255], [0; 32]).0, hanging);
+    }
+
+    #[test]
+    fn test_random_depths() {
+        // a limit of 0 moves tries them all, rather than none
+        let board = parse_fen("4k3/8/2p5/3p4/8/8/8/3QK3 w - - 0 1").unwrap();
+        let all = board.get_next_move(&[255, 255], [0; 32]);
+        let unlimited = board.get_next_move(&[0, 0], [0; 32]);
+        assert_eq!(unlimited.2, all.2);
+        assert_ne!(unlimited.0, Move::Piece(D1, D5));
+    }
+
+    #[test]
+    fn test_move_ordering() {
+        let board = parse_fen("4k3/8/8/3q4/4P3/8/8/3QK3 w - - 0 1").unwrap();
+        let mut searcher = Searcher::default();
+        searcher.killers.push([Some(Move::Piece(D1, A4)), None]);
+        searcher.history_table[square_index(D1)][square_index(B3)] = 10;
+        let moves = searcher.order_moves(
+            &board,
+            board.get_legal_moves().collect(),
+            Some(Move::Piece(E1, F2)),
+        );
+        assert_eq!(
+            moves[..5],
+            [
+                Move::Piece(E1, F2),
+                // the pawn takes the queen before the queen does
+                Move::Piece(E4, D5),
+                Move::Piece(D1, D5),
+                Move::Piece(D1, A4),
+                Move::Piece(D1, B3),
+            ]
+        );
+
+        // cutoffs by quiet moves are remembered for the ply
+        searcher.record_cutoff(&board, Move::Piece(D1, H5), 3, 1);
+        searcher.record_cutoff(&board, Move::Piece(E4, D5), 3, 1);
+        assert_eq!(searcher.killers[1], [Some(Move::Piece(D1, H5)), None]);
+        assert_eq!(
+            searcher.history_table[square_index(D1)][square_index(H5)],
+            9
+        );
+    }
+
+    #[test]
+    fn test_searcher_tables() {
+        // searching without a transposition table allocates no tables
+        let searcher = Searcher::new(TranspositionTable::from_buffer(&mut []));
+        assert_eq!(searcher.pawn_table.capacity(), 0);
+        let params = EvalParams::default();
+        let searcher = Searcher::default().set_params(&params);
+        assert_eq!(searcher.pawn_table.capacity(), PAWN_TABLE_SIZE);
+        assert_eq!(
+            searcher.get_table().capacity(),
+            TranspositionTable::default().capacity()
+        );
+    }
+
+    #[test]
+    fn test_killer_plies() {
+        // the white king can't leave the corner around h1 and the black
+        // pieces can't reach it in the search, so every move shows which
+        // player made it
+        let board = parse_fen("k7/r7/8/8/8/8/8/7K w - - 0 1").unwrap();
+        let mut searcher = Searcher::default();
+        searcher.search(
+            &board,
+            SearchLimits {
+                depth: Some(4),
+                ..SearchLimits::default()
+            },
+            |_| {},
+        );
+
+        // the killers of a ply are moves of the player to move at that
+        // ply, so they're tried first among that player's moves
+        assert!(searcher.killers.iter().flatten().any(Option::is_some));
+        for (ply, killers) in searcher.killers.iter().enumerate() {
+            for m in killers.iter().flatten() {
+                let from = match m {
+                    Move::Piece(from, _) => *from,
+                    _ => panic!("unexpected killer {:?}", m),
+                };
+                assert_eq!(from.get_col() >= 5, ply % 2 == 0, "{:?} at ply {}", m, ply);
+            }
+        }
+    }
+
+    #[test]
+    fn test_principal_variation() {
+        let board =
+            parse_fen("r1bqkbnr/pppp1ppp/2n5/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R b KQkq - 3 3").unwrap();
+        let result = board.search(SearchLimits {
+            depth: Some(4),
+            ..SearchLimits::default()
+        });
+        assert_eq!(result.depth, 4);
+        assert!(result.seldepth >= 4);
+        assert_eq!(result.pv[0], result.best_move);
+        let mut position = board;
+        for &m in &result.pv {
+            assert!(position.get_legal_moves().any(|legal| legal == m));
+            position = position.apply_eval_move(m);
+        }
+
+        // back rank mate
+        let board = parse_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        let result = board.search(SearchLimits {
+            depth: Some(3),
+            ..SearchLimits::default()
+        });
+        assert_eq!(result.pv, [Move::Piece(A1, A8)]);
+        assert_eq!(result.score, Score::Mate(1));
+        assert_eq!(result.score.to_string(), "#1");
+        assert_eq!(Score::Centipawns(-35).to_string(), "-0.35");
+    }
+
+    #[test]
+    fn test_multipv() {
+        let board = Board::default();
+        let limits = SearchLimits {
+            depth: Some(3),
+            ..SearchLimits::default()
+        };
+        let lines = board.analyse_multipv(limits, 3);
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0].best_move, board.search(limits).best_move);
+        for pair in lines.windows(2) {
+            assert_ne!(pair[0].best_move, pair[1].best_move);
+            assert!(pair[0].score >= pair[1].score);
+        }
+        for line in &lines {
+            assert_eq!(line.pv[0], line.best_move);
+        }
+
+        // only as many lines as there are moves
+        let board = parse_fen("k7/8/8/8/8/8/8/7K w - - 0 1").unwrap();
+        assert_eq!(board.analyse_multipv(limits, 5).len(), 3);
+
+        assert!(Score::Mate(1) > Score::Mate(3));
+        assert!(Score::Mate(3) > Score::Centipawns(900));
+        assert!(Score::Centipawns(-900) > Score::Mate(-5));
+        assert!(Score::Mate(-5) > Score::Mate(-1));
+    }
+
+    #[test]
+    fn test_mate_scores() {
+        // Qb8# mates at once, while other checks take longer
+        let board = parse_fen("6k1/5ppp/8/8/8/8/1Q6/R5K1 w - - 0 1").unwrap();
+        let result = board.search(SearchLimits {
+            depth: Some(4),
+            ..SearchLimits::default()
+        });
+        assert_eq!(result.score, Score::Mate(1));
+        assert!(board.apply_eval_move(result.best_move).is_checkmate());
+
+        // Bxd8 is forced, and then Re8# follows
+        let board = parse_fen("r1bQ1k1r/ppp1bppp/8/1B6/5q2/2P5/PPP2PPP/R3R1K1 b - - 1 1").unwrap();
+        let result = board.search(SearchLimits {
+            depth: Some(4),
+            ..SearchLimits::default()
+        });
+        assert_eq!(result.score, Score::Mate(-1));
+
+        // stalemate is a draw, so the king isn't boxed in
+        let board = parse_fen("7k/8/5K2/8/8/8/8/6Q1 w - - 0 1").unwrap();
+        let stalemate = Move::Piece(G1, G6);
+        assert!(board.apply_eval_move(stalemate).is_stalemate());
+        assert_ne!(board.get_best_next_move(2).0, stalemate);
+        let stalemated = board.apply_eval_move(stalemate);
+        assert_eq!(
+            stalemated.search(SearchLimits::default()).score,
+            Score::Centipawns(0)
+        );
+    }
+
+    #[test]
+    fn test_find_mate() {
+        // a mate in two, Qd8+ Bxd8 Re8#
+        let board = parse_fen("r1b2k1r/ppp1bppp/8/1B1Q4/5q2/2P5/PPP2PPP/R3R1K1 w - - 0 1").unwrap();
+        assert_eq!(board.find_mate(1), None);
+        let line = board.find_mate(2).unwrap();
+        assert_eq!(line.len(), 3);
+        assert_eq!(line[0], Move::Piece(D5, D8));
+        // a longer limit still finds the shortest mate
+        assert_eq!(board.find_mate(3), Some(line.clone()));
+        let mut position = board;
+        for m in line {
+            position = position.apply_eval_move(m);
+        }
+        assert!(position.is_checkmate());
+
+        assert_eq!(Board::default().find_mate(2), None);
+    }
+
+    #[test]
+    fn test_search_stop() {
+        // a cancelled search still completes its first iteration
+        let stop = AtomicBool::new(true);
+        let mut searcher = Searcher::default().set_stop(&stop);
+        let mut depths = vec![];
+        let result = searcher.search(&Board::default(), SearchLimits::default(), |result| {
+            depths.push(result.depth)
+        });
+        assert_eq!(depths, [1]);
+        assert_eq!(result.nodes, 20);
+        assert!(Board::default()
+            .get_legal_moves()
+            .any(|m| m == result.best_move));
+    }
+}
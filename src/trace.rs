@@ -0,0 +1,177 @@
+use super::*;
+
+/// The contribution of one term of the evaluation for each player, as
+/// (middlegame, endgame) pairs in centipawns.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TermTrace {
+    pub white: (i32, i32),
+    pub black: (i32, i32),
+}
+
+impl TermTrace {
+    #[inline]
+    pub(crate) fn add(&mut self, color: Color, (middlegame, endgame): (i32, i32)) {
+        let score = match color {
+            WHITE => &mut self.white,
+            BLACK => &mut self.black,
+        };
+        score.0 += middlegame;
+        score.1 += endgame;
+    }
+
+    /// The score of `color` minus the score of the other player.
+    pub fn get_difference(&self, color: Color) -> (i32, i32) {
+        let (ally, enemy) = match color {
+            WHITE => (self.white, self.black),
+            BLACK => (self.black, self.white),
+        };
+        (ally.0 - enemy.0, ally.1 - enemy.1)
+    }
+}
+
+/// A breakdown of the value of a board into the terms of the evaluation,
+/// returned by `Board::eval_trace`.
+///
+/// Each term holds the scores of both players' own pieces, before they're
+/// blended by the game phase. `get_total` blends and adds them up to the
+/// value from `Board::value_for`, and the `Display` implementation prints
+/// them as a table in pawns.
+///
+/// ```rust
+/// use chess_engine::{Board, WHITE};
+///
+/// let board = Board::default();
+/// let trace = board.eval_trace(WHITE);
+/// assert_eq!(trace.get_total(), board.value_for(WHITE));
+/// assert_eq!(trace.material.white, trace.material.black);
+/// println!("{}", trace);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EvalTrace {
+    /// The player the board is valued for.
+    pub color: Color,
+    /// The game phase of the board, from `max_phase` in the opening down
+    /// to 0 in the endgame.
+    pub phase: i32,
+    pub max_phase: i32,
+    /// The material value of the pieces.
+    pub material: TermTrace,
+    /// The weights of the squares the pieces stand on.
+    pub squares: TermTrace,
+    /// Doubled, isolated, backward and passed pawns.
+    pub pawn_structure: TermTrace,
+    /// The squares the pieces control.
+    pub mobility: TermTrace,
+    /// Attacks around the king and the pawns sheltering it.
+    pub king_safety: TermTrace,
+}
+
+impl EvalTrace {
+    pub(crate) fn new(color: Color) -> Self {
+        Self {
+            color,
+            phase: 0,
+            max_phase: 0,
+            material: TermTrace::default(),
+            squares: TermTrace::default(),
+            pawn_structure: TermTrace::default(),
+            mobility: TermTrace::default(),
+            king_safety: TermTrace::default(),
+        }
+    }
+
+    /// Every term with its name, in the order they're printed.
+    pub fn get_terms(&self) -> [(&'static str, TermTrace); 5] {
+        [
+            ("Material", self.material),
+            ("Piece squares", self.squares),
+            ("Pawn structure", self.pawn_structure),
+            ("Mobility", self.mobility),
+            ("King safety", self.king_safety),
+        ]
+    }
+
+    /// Blend a (middlegame, endgame) pair by the game phase of the board.
+    pub fn blend(&self, (middlegame, endgame): (i32, i32)) -> i32 {
+        if self.max_phase <= 0 {
+            // no piece counts towards the phase, so it never changes
+            return middlegame;
+        }
+        let phase = self.phase.min(self.max_phase);
+        (middlegame * phase + endgame * (self.max_phase - phase)) / self.max_phase
+    }
+
+    /// The value of the board for `color`, which is the same as the value
+    /// from `Board::value_for`.
+    pub fn get_total(&self) -> i32 {
+        let (mut middlegame, mut endgame) = (0, 0);
+        for (_, term) in self.get_terms().iter() {
+            let (term_middlegame, term_endgame) = term.get_difference(self.color);
+            middlegame += term_middlegame;
+            endgame += term_endgame;
+        }
+        self.blend((middlegame, endgame))
+    }
+}
+
+impl core::fmt::Display for EvalTrace {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> Result<(), core::fmt::Error> {
+        writeln!(
+            f,
+            "{:<16}{:>10}{:>10}{:>10}",
+            "Term",
+            "White",
+            "Black",
+            self.color.to_string()
+        )?;
+        for (name, term) in self.get_terms().iter() {
+            writeln!(
+                f,
+                "{:<16}{:>+10.2}{:>+10.2}{:>+10.2}",
+                name,
+                centipawns_to_pawns(self.blend(term.white)),
+                centipawns_to_pawns(self.blend(term.black)),
+                centipawns_to_pawns(self.blend(term.get_difference(self.color))),
+            )?;
+        }
+        writeln!(
+            f,
+            "{:<36}{:>+10.2}",
+            format!("Total (phase {}/{})", self.phase, self.max_phase),
+            centipawns_to_pawns(self.get_total())
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eval_trace() {
+        // white is a knight up, with black's king out in the open
+        let board =
+            parse_fen("r1bq1bnr/ppp2ppp/3k4/3p4/3P4/2N2N2/PPP2PPP/R1BQKB1R b KQ - 0 1").unwrap();
+        for &color in [WHITE, BLACK].iter() {
+            let trace = board.eval_trace(color);
+            assert_eq!(trace.get_total(), board.value_for(color));
+            assert_eq!(trace.color, color);
+        }
+
+        let trace = board.eval_trace(WHITE);
+        let knight = EvalParams::DEFAULT.material[4];
+        assert_eq!(trace.material.get_difference(WHITE), (knight, knight));
+        assert!(trace.king_safety.white.0 > trace.king_safety.black.0);
+        assert_eq!(trace.king_safety.white.1, 0);
+        assert!(trace.mobility.get_difference(WHITE).0 > 0);
+        assert_eq!(trace.phase, board.get_game_phase());
+
+        let params = EvalParams::default();
+        assert_eq!(board.eval_trace_with(&params, WHITE), trace);
+
+        let table = trace.to_string();
+        assert!(table.starts_with("Term"));
+        assert!(table.contains("King safety"));
+        assert!(table.lines().last().unwrap().starts_with("Total (phase"));
+    }
+}
@@ -0,0 +1,575 @@
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use crate::board::Board;
+use crate::pgn::{
+    self, push_comment, starting_board, tokenize, wrap_movetext, write_tags, PgnError, Token,
+};
+use crate::util::{parse_san_move, SanError};
+use crate::{GameResult, Move, WHITE};
+
+// index of a node in a GameTree
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NodeId(usize);
+
+// a position in a GameTree, reached by playing `chess_move` from the parent
+//
+// the first child continues the line this node is on, any other children
+// are variations, in order of preference.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameNode {
+    board: Board,
+    chess_move: Option<Move>,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+    // pgn commentary following the move, or before the first move at the root
+    pub comment: Option<String>,
+    // pgn commentary before the move, as at the start of a variation
+    pub comment_before: Option<String>,
+    // pgn numeric annotation glyphs, e.g. 1 for "!" and 2 for "?"
+    pub nags: Vec<u8>,
+}
+
+impl GameNode {
+    // position after the move
+    pub fn get_board(&self) -> &Board {
+        &self.board
+    }
+
+    // move leading to this node, None for the root
+    pub fn get_move(&self) -> Option<Move> {
+        self.chess_move
+    }
+
+    pub fn get_parent(&self) -> Option<NodeId> {
+        self.parent
+    }
+
+    // continuation first, then variations
+    pub fn get_children(&self) -> &[NodeId] {
+        &self.children
+    }
+}
+
+// a game with variations, as written in pgn with ( ... )
+//
+// nodes live in an arena and are addressed by NodeId. ids of deleted nodes
+// are never reused, so looking them up returns None.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameTree {
+    // pgn tag pairs, in the order they were read or set
+    pub tags: Vec<(String, String)>,
+    nodes: Vec<Option<GameNode>>,
+    current: NodeId,
+}
+
+impl Default for GameTree {
+    fn default() -> Self {
+        Self::new(Board::default())
+    }
+}
+
+impl GameTree {
+    pub fn new(board: Board) -> Self {
+        GameTree {
+            tags: Vec::new(),
+            nodes: vec![Some(GameNode {
+                board,
+                chess_move: None,
+                parent: None,
+                children: Vec::new(),
+                comment: None,
+                comment_before: None,
+                nags: Vec::new(),
+            })],
+            current: NodeId(0),
+        }
+    }
+
+    // node holding the starting position
+    pub fn root(&self) -> NodeId {
+        NodeId(0)
+    }
+
+    pub fn get_node(&self, id: NodeId) -> Option<&GameNode> {
+        self.nodes.get(id.0).and_then(Option::as_ref)
+    }
+
+    pub fn get_node_mut(&mut self, id: NodeId) -> Option<&mut GameNode> {
+        self.nodes.get_mut(id.0).and_then(Option::as_mut)
+    }
+
+    // node being looked at, where navigation starts from
+    pub fn current(&self) -> NodeId {
+        self.current
+    }
+
+    // position at the current node
+    pub fn get_board(&self) -> &Board {
+        &self.node(self.current).board
+    }
+
+    // value of a pgn tag such as "Event" or "White"
+    pub fn get_tag(&self, name: &str) -> Option<&str> {
+        pgn::get_tag(&self.tags, name)
+    }
+
+    // add a pgn tag, or replace the value of an existing one
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        pgn::set_tag(&mut self.tags, name, value)
+    }
+
+    fn node(&self, id: NodeId) -> &GameNode {
+        self.get_node(id).expect("node was deleted")
+    }
+
+    fn node_mut(&mut self, id: NodeId) -> &mut GameNode {
+        self.get_node_mut(id).expect("node was deleted")
+    }
+
+    // play a move from `parent`, returning the node it leads to
+    //
+    // a move that was already played from `parent` returns the existing
+    // node; otherwise the move becomes the continuation if `parent` has
+    // none, or its last variation. None if the move is illegal or
+    // `parent` was deleted.
+    pub fn add_move(&mut self, parent: NodeId, chess_move: Move) -> Option<NodeId> {
+        let board = self.get_node(parent)?.board;
+        if let Some(&existing) = self
+            .node(parent)
+            .children
+            .iter()
+            .find(|&&child| self.node(child).chess_move == Some(chess_move))
+        {
+            return Some(existing);
+        }
+        if let Move::Resign = chess_move {
+            return None;
+        }
+        if let GameResult::IllegalMove(_) = board.play_move(chess_move) {
+            return None;
+        }
+
+        let id = NodeId(self.nodes.len());
+        self.nodes.push(Some(GameNode {
+            board: board.apply_eval_move(chess_move),
+            chess_move: Some(chess_move),
+            parent: Some(parent),
+            children: Vec::new(),
+            comment: None,
+            comment_before: None,
+            nags: Vec::new(),
+        }));
+        self.node_mut(parent).children.push(id);
+        Some(id)
+    }
+
+    // like add_move, using san notation
+    pub fn add_san(&mut self, parent: NodeId, san: &str) -> Result<NodeId, SanError> {
+        let board = match self.get_node(parent) {
+            Some(node) => node.board,
+            None => return Err(SanError::Illegal),
+        };
+        let chess_move = parse_san_move(&board, san)?;
+        self.add_move(parent, chess_move).ok_or(SanError::Illegal)
+    }
+
+    // play a san move from the current node and go to it
+    pub fn play(&mut self, san: &str) -> Result<NodeId, SanError> {
+        let id = self.add_san(self.current, san)?;
+        self.current = id;
+        Ok(id)
+    }
+
+    // move a variation one place closer to being the continuation,
+    // returns false if it already is, or is the root
+    pub fn promote_variation(&mut self, id: NodeId) -> bool {
+        let parent = match self.get_node(id).and_then(|node| node.parent) {
+            Some(parent) => parent,
+            None => return false,
+        };
+        let siblings = &mut self.node_mut(parent).children;
+        match siblings.iter().position(|&child| child == id) {
+            Some(index) if index > 0 => {
+                siblings.swap(index, index - 1);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // make the line through `id` the mainline of the game
+    pub fn promote_to_mainline(&mut self, id: NodeId) {
+        let mut node = id;
+        while let Some(parent) = self.get_node(node).and_then(|node| node.parent) {
+            let siblings = &mut self.node_mut(parent).children;
+            if let Some(index) = siblings.iter().position(|&child| child == node) {
+                let promoted = siblings.remove(index);
+                siblings.insert(0, promoted);
+            }
+            node = parent;
+        }
+    }
+
+    // remove a node and everything after it, returns false for the root
+    //
+    // if the current node is removed, its deepest remaining ancestor
+    // becomes current.
+    pub fn delete_variation(&mut self, id: NodeId) -> bool {
+        let parent = match self.get_node(id).and_then(|node| node.parent) {
+            Some(parent) => parent,
+            None => return false,
+        };
+        self.node_mut(parent).children.retain(|&child| child != id);
+
+        if self.path_to(self.current).contains(&id) {
+            self.current = parent;
+        }
+        let mut stack = vec![id];
+        while let Some(node) = stack.pop() {
+            if let Some(removed) = self.nodes[node.0].take() {
+                stack.extend(removed.children);
+            }
+        }
+        true
+    }
+
+    // make `id` the current node, returns false if it was deleted
+    pub fn go_to(&mut self, id: NodeId) -> bool {
+        if self.get_node(id).is_none() {
+            return false;
+        }
+        self.current = id;
+        true
+    }
+
+    // go to the previous position, returns false at the root
+    pub fn go_back(&mut self) -> bool {
+        match self.node(self.current).parent {
+            Some(parent) => {
+                self.current = parent;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // go to the continuation of the current line, returns false at its end
+    pub fn go_forward(&mut self) -> bool {
+        match self.node(self.current).children.first() {
+            Some(&child) => {
+                self.current = child;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // nodes from the root to `id`, both included
+    pub fn path_to(&self, id: NodeId) -> Vec<NodeId> {
+        let mut path = vec![];
+        let mut node = Some(id);
+        while let Some(id) = node {
+            match self.get_node(id) {
+                Some(game_node) => {
+                    path.push(id);
+                    node = game_node.parent;
+                }
+                None => return Vec::new(),
+            }
+        }
+        path.reverse();
+        path
+    }
+
+    // nodes of the mainline after the root
+    pub fn mainline(&self) -> Vec<NodeId> {
+        let mut line = vec![];
+        let mut node = self.root();
+        while let Some(&child) = self.node(node).children.first() {
+            line.push(child);
+            node = child;
+        }
+        line
+    }
+
+    // read the first game from pgn text, including its variations
+    pub fn from_pgn(pgn: &str) -> Result<Self, PgnError> {
+        let tokens = tokenize(pgn)?;
+        if tokens.is_empty() {
+            return Err(PgnError::Malformed(String::from("no game")));
+        }
+
+        let mut tags = vec![];
+        let mut movetext = &tokens[..];
+        while let Some((Token::Tag(name, value), rest)) = movetext.split_first() {
+            tags.push((name.to_string(), value.clone()));
+            movetext = rest;
+        }
+
+        let mut tree = GameTree::new(starting_board(&tags)?);
+        tree.tags = tags;
+
+        // node the next move is played from, and where to return to after
+        // each open variation
+        let mut node = tree.root();
+        let mut variations = vec![];
+        // a comment at the start of a variation, before its first move
+        let mut pending_comment: Option<String> = None;
+        let mut at_variation_start = false;
+        for token in movetext {
+            match token {
+                Token::Tag(_, _) => {
+                    return Err(PgnError::Malformed(String::from("tag in movetext")));
+                }
+                Token::MoveNumber => {}
+                Token::San(san) => {
+                    node = tree
+                        .add_san(node, san)
+                        .map_err(|error| PgnError::InvalidMove(san.to_string(), error))?;
+                    at_variation_start = false;
+                    if let Some(comment) = pending_comment.take() {
+                        append_comment(&mut tree.node_mut(node).comment_before, &comment);
+                    }
+                }
+                Token::Nag(_) if at_variation_start => {
+                    return Err(PgnError::Malformed(String::from(
+                        "NAG before the first move of a variation",
+                    )));
+                }
+                Token::Nag(nag) => tree.node_mut(node).nags.push(*nag),
+                Token::Comment(comment) if at_variation_start => {
+                    append_comment(&mut pending_comment, comment)
+                }
+                Token::Comment(comment) => {
+                    append_comment(&mut tree.node_mut(node).comment, comment)
+                }
+                Token::StartVariation => {
+                    // a variation replaces the last move played
+                    let parent = tree.node(node).parent.ok_or_else(|| {
+                        PgnError::Malformed(String::from("variation before first move"))
+                    })?;
+                    variations.push(node);
+                    node = parent;
+                    at_variation_start = true;
+                }
+                Token::EndVariation => {
+                    node = variations
+                        .pop()
+                        .ok_or_else(|| PgnError::Malformed(String::from("unopened variation")))?;
+                    at_variation_start = false;
+                    // a variation without moves leaves its comment on the
+                    // move it was an alternative to
+                    if let Some(comment) = pending_comment.take() {
+                        append_comment(&mut tree.node_mut(node).comment, &comment);
+                    }
+                }
+                Token::Result(result) if variations.is_empty() => {
+                    tree.set_tag("Result", result);
+                    break;
+                }
+                Token::Result(_) => {
+                    return Err(PgnError::Malformed(String::from("unclosed variation")));
+                }
+            }
+        }
+        if !variations.is_empty() {
+            return Err(PgnError::Malformed(String::from("unclosed variation")));
+        }
+
+        Ok(tree)
+    }
+
+    // write the whole tree as pgn, with the result taken from the Result tag
+    pub fn to_pgn(&self) -> String {
+        let result = self.get_tag("Result").unwrap_or("*");
+        let root = self.node(self.root());
+
+        let mut pgn = String::new();
+        write_tags(&mut pgn, &self.tags, &root.board, result);
+        pgn.push('\n');
+
+        let mut movetext = vec![];
+        for nag in &root.nags {
+            movetext.push(format!("${}", nag));
+        }
+        if let Some(comment) = &root.comment {
+            push_comment(&mut movetext, comment);
+        }
+        self.write_line(self.root(), true, &mut movetext);
+        movetext.push(result.to_string());
+        wrap_movetext(&mut pgn, &movetext);
+
+        pgn
+    }
+
+    // write the line continuing from `from`, with the variations of each move
+    fn write_line(&self, from: NodeId, mut needs_number: bool, movetext: &mut Vec<String>) {
+        let mut node = from;
+        while let Some(&continuation) = self.node(node).children.first() {
+            needs_number = self.write_move(continuation, needs_number, movetext);
+            for &variation in &self.node(node).children[1..] {
+                let start = movetext.len();
+                let variation_needs_number = self.write_move(variation, true, movetext);
+                movetext[start].insert(0, '(');
+                self.write_line(variation, variation_needs_number, movetext);
+                if let Some(last) = movetext.last_mut() {
+                    last.push(')');
+                }
+                needs_number = true;
+            }
+            node = continuation;
+        }
+    }
+
+    // write a move with its number, NAGs and comment, returns whether the
+    // next move needs a number, because black's moves normally have none
+    fn write_move(&self, id: NodeId, needs_number: bool, movetext: &mut Vec<String>) -> bool {
+        let node = self.node(id);
+        let before = &self.node(node.parent.unwrap_or(id)).board;
+        let number = before.get_fullmove_number();
+        if let Some(comment) = &node.comment_before {
+            push_comment(movetext, comment);
+        }
+        if before.get_turn_color() == WHITE {
+            movetext.push(format!("{}.", number));
+        } else if needs_number || node.comment_before.is_some() {
+            movetext.push(format!("{}...", number));
+        }
+        if let Some(chess_move) = node.chess_move {
+            movetext.push(before.to_san(chess_move));
+        }
+        for nag in &node.nags {
+            movetext.push(format!("${}", nag));
+        }
+        if let Some(comment) = &node.comment {
+            push_comment(movetext, comment);
+        }
+        node.comment.is_some()
+    }
+}
+
+// add `text` after any comment there already is
+fn append_comment(comment: &mut Option<String>, text: &str) {
+    *comment = Some(match comment.take() {
+        Some(previous) => previous + " " + text,
+        None => text.to_string(),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn san_line(tree: &GameTree, line: &[NodeId]) -> Vec<String> {
+        line.iter()
+            .map(|&id| {
+                let node = tree.get_node(id).unwrap();
+                let before = tree.get_node(node.get_parent().unwrap()).unwrap();
+                before.get_board().to_san(node.get_move().unwrap())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_tree_variations() {
+        let mut tree = GameTree::default();
+        let root = tree.root();
+        let e4 = tree.play("e4").unwrap();
+        let e5 = tree.play("e5").unwrap();
+        let d4 = tree.add_san(root, "d4").unwrap();
+        let c5 = tree.add_san(e4, "c5").unwrap();
+        let e6 = tree.add_san(e4, "e6").unwrap();
+
+        assert_eq!(tree.add_san(root, "e4"), Ok(e4));
+        assert_eq!(tree.add_san(e4, "e4"), Err(SanError::Illegal));
+        assert_eq!(tree.get_node(e4).unwrap().get_children(), &[e5, c5, e6]);
+        assert_eq!(tree.current(), e5);
+
+        assert!(tree.promote_variation(e6));
+        assert_eq!(tree.get_node(e4).unwrap().get_children(), &[e5, e6, c5]);
+        assert!(!tree.promote_variation(e5));
+
+        tree.promote_to_mainline(c5);
+        assert_eq!(tree.mainline(), vec![e4, c5]);
+        assert_eq!(san_line(&tree, &tree.path_to(c5)[1..]), vec!["e4", "c5"]);
+
+        assert!(tree.delete_variation(e5));
+        assert!(tree.get_node(e5).is_none());
+        assert_eq!(tree.current(), e4);
+        assert!(!tree.delete_variation(root));
+        assert!(!tree.go_to(e5));
+
+        assert!(tree.go_to(d4));
+        assert!(!tree.go_forward());
+        assert!(tree.go_back());
+        assert!(tree.go_forward());
+        assert_eq!(tree.current(), e4);
+    }
+
+    #[test]
+    fn test_tree_pgn() {
+        let pgn = "[Event \"Analysis\"]\n\n\
+                   {Open games} 1. e4 e5 (1... c5 {Sicilian} 2. Nf3 (2. c3 d5) 2... d6)\n\
+                   (1... e6 $2) 2. Nf3 Nc6 3. Bb5 1-0";
+        let tree = GameTree::from_pgn(pgn).unwrap();
+        assert_eq!(tree.get_tag("Result"), Some("1-0"));
+        assert_eq!(
+            san_line(&tree, &tree.mainline()),
+            vec!["e4", "e5", "Nf3", "Nc6", "Bb5"]
+        );
+        let e4 = tree.mainline()[0];
+        assert_eq!(tree.get_node(e4).unwrap().get_children().len(), 3);
+
+        assert_eq!(
+            tree.to_pgn(),
+            "[Event \"Analysis\"]\n\
+             [Site \"?\"]\n\
+             [Date \"????.??.??\"]\n\
+             [Round \"?\"]\n\
+             [White \"?\"]\n\
+             [Black \"?\"]\n\
+             [Result \"1-0\"]\n\
+             \n\
+             {Open games} 1. e4 e5 (1... c5 {Sicilian} 2. Nf3 (2. c3 d5) 2... d6) (1... e6\n\
+             $2) 2. Nf3 Nc6 3. Bb5 1-0\n"
+        );
+        let reread = GameTree::from_pgn(&tree.to_pgn()).unwrap();
+        assert_eq!(reread.to_pgn(), tree.to_pgn());
+        assert_eq!(reread.get_node(e4), tree.get_node(e4));
+
+        // a comment opening a variation comes before its first move, not
+        // after the move it replaces
+        let movetext =
+            "$1 {Notes} 1. e4 {King's pawn} ({Alternatively} 1. d4) 1... e5 ({Or} 1... c5 2.\n\
+                        Nf3) *\n";
+        let tree = GameTree::from_pgn(movetext).unwrap();
+        let root = tree.get_node(tree.root()).unwrap();
+        assert_eq!(root.nags, vec![1]);
+        let e4 = tree.get_node(tree.mainline()[0]).unwrap();
+        assert_eq!(e4.comment, Some(String::from("King's pawn")));
+        assert_eq!(e4.comment_before, None);
+        let d4 = tree.get_node(root.get_children()[1]).unwrap();
+        assert_eq!(d4.comment, None);
+        assert_eq!(d4.comment_before, Some(String::from("Alternatively")));
+        assert!(tree.to_pgn().ends_with(&format!("\n{}", movetext)));
+        let reread = GameTree::from_pgn(&tree.to_pgn()).unwrap();
+        assert_eq!(reread.to_pgn(), tree.to_pgn());
+
+        assert_eq!(
+            GameTree::from_pgn("1. e4 ($2 1. d4) *"),
+            Err(PgnError::Malformed(String::from(
+                "NAG before the first move of a variation"
+            )))
+        );
+
+        assert!(matches!(
+            GameTree::from_pgn("1. e4 (1. d4 d5"),
+            Err(PgnError::Malformed(_))
+        ));
+        assert!(matches!(
+            GameTree::from_pgn("(1. e4) *"),
+            Err(PgnError::Malformed(_))
+        ));
+    }
+}
@@ -0,0 +1,336 @@
+use super::*;
+use either::Either;
+
+/// The size in bytes of `TranspositionTable::default()`, the table used by
+/// `Searcher::default()`, `Board::search` and `Board::analyse_multipv`.
+pub const DEFAULT_TABLE_SIZE: usize = 1 << 20;
+
+/// The size in bytes of the table that `Board::get_best_next_move`,
+/// `get_worst_next_move` and `minimax` allocate for each call. Their searches are shallow, so a small table catches most of the
+/// transpositions in them.
+pub const SMALL_TABLE_SIZE: usize = 1 << 18;
+
+/// How the score stored in a `TtEntry` relates to the true score of the
+/// position.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bound {
+    /// The score is exact.
+    Exact,
+    /// The search failed high, so the true score is at least this.
+    Lower,
+    /// The search failed low, so the true score is at most this.
+    Upper,
+}
+
+/// A position remembered by a `TranspositionTable`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TtEntry {
+    key: u64,
+    depth: u8,
+    bound: Option<Bound>,
+    score: i32,
+    best_move: Move,
+}
+
+impl TtEntry {
+    /// An unused slot, for filling caller-provided buffers.
+    pub const EMPTY: Self = Self {
+        key: 0,
+        depth: 0,
+        bound: None,
+        score: 0,
+        best_move: Move::Resign,
+    };
+
+    /// The number of moves of lookahead the score was searched with.
+    #[inline]
+    pub fn get_depth(&self) -> u8 {
+        self.depth
+    }
+
+    #[inline]
+    pub fn get_bound(&self) -> Bound {
+        self.bound.unwrap_or(Bound::Exact)
+    }
+
+    /// The score of the position in centipawns for the player whose turn it
+    /// is.
+    #[inline]
+    pub fn get_score(&self) -> i32 {
+        self.score
+    }
+
+    /// The best move found, which is searched first when the position
+    /// is seen again.
+    #[inline]
+    pub fn get_best_move(&self) -> Move {
+        self.best_move
+    }
+
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.bound.is_none()
+    }
+}
+
+impl Default for TtEntry {
+    fn default() -> Self {
+        Self::EMPTY
+    }
+}
+
+/// A fixed-size cache of searched positions, indexed by their Zobrist hash.
+///
+/// When the search reaches a position a second time, possibly through a
+/// different order of moves, the stored score can end the search of that
+/// position early, and the stored best move is tried first.
+///
+/// The table either owns its entries, or uses a buffer provided by the
+/// caller, so that it can be used without allocating.
+///
+/// ```rust
+/// use chess_engine::{Board, TranspositionTable, TtEntry};
+///
+/// let mut buffer = [TtEntry::EMPTY; 1024];
+/// let mut table = TranspositionTable::from_buffer(&mut buffer);
+/// let (best_move, _, _) = Board::default().get_best_next_move_with_table(3, &[], &mut table);
+/// assert_eq!(table.probe(&Board::default()).unwrap().get_best_move(), best_move);
+/// ```
+pub struct TranspositionTable<'a> {
+    entries: Either<Vec<TtEntry>, &'a mut [TtEntry]>,
+}
+
+impl TranspositionTable<'static> {
+    /// Create a table that uses at most `size` bytes.
+    pub fn new(size: usize) -> Self {
+        let len = size / core::mem::size_of::<TtEntry>();
+        Self {
+            entries: Either::Left(vec![TtEntry::EMPTY; len]),
+        }
+    }
+}
+
+impl Default for TranspositionTable<'static> {
+    fn default() -> Self {
+        Self::new(DEFAULT_TABLE_SIZE)
+    }
+}
+
+impl<'a> TranspositionTable<'a> {
+    /// Create a table that stores its entries in `buffer`. Any entries
+    /// already in the buffer are forgotten.
+    pub fn from_buffer(buffer: &'a mut [TtEntry]) -> Self {
+        let mut table = Self {
+            entries: Either::Right(buffer),
+        };
+        table.clear();
+        table
+    }
+
+    /// The number of positions the table can hold.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.entries().len()
+    }
+
+    /// Forget every stored position.
+    pub fn clear(&mut self) {
+        for entry in self.entries_mut() {
+            *entry = TtEntry::EMPTY;
+        }
+    }
+
+    /// Turn the table into one that owns its entries, copying them out of
+    /// the buffer if it was created with `from_buffer`. This gives back a
+    /// table created with `new` from a `Searcher` that borrowed other
+    /// values for a shorter time.
+    pub fn into_owned(self) -> TranspositionTable<'static> {
+        TranspositionTable {
+            entries: match self.entries {
+                Either::Left(entries) => Either::Left(entries),
+                Either::Right(entries) => Either::Left(entries.to_vec()),
+            },
+        }
+    }
+
+    /// How full the table is, in permille, estimated from the first
+    /// thousand slots.
+    pub fn hashfull(&self) -> u16 {
+        let sample = &self.entries()[..self.capacity().min(1000)];
+        if sample.is_empty() {
+            return 0;
+        }
+        let used = sample.iter().filter(|entry| !entry.is_empty()).count();
+        (used * 1000 / sample.len()) as u16
+    }
+
+    /// Look up the entry stored for `board`.
+    pub fn probe(&self, board: &Board) -> Option<&TtEntry> {
+        let key = board.zobrist_hash();
+        self.index(key)
+            .map(|index| &self.entries()[index])
+            .filter(|entry| !entry.is_empty() && entry.key == key)
+    }
+
+    /// Remember the result of searching `board`. An entry for the same
+    /// position is only replaced by one searched at least as deep.
+    pub fn store(&mut self, board: &Board, depth: u8, bound: Bound, score: i32, best_move: Move) {
+        let key = board.zobrist_hash();
+        let index = match self.index(key) {
+            Some(index) => index,
+            None => return,
+        };
+
+        let entry = &mut self.entries_mut()[index];
+        if entry.is_empty() || entry.key != key || depth >= entry.depth {
+            *entry = TtEntry {
+                key,
+                depth,
+                bound: Some(bound),
+                score,
+                best_move,
+            };
+        }
+    }
+
+    #[inline]
+    fn index(&self, key: u64) -> Option<usize> {
+        match self.capacity() {
+            0 => None,
+            len => Some((key % len as u64) as usize),
+        }
+    }
+
+    #[inline]
+    fn entries(&self) -> &[TtEntry] {
+        match &self.entries {
+            Either::Left(entries) => entries,
+            Either::Right(entries) => entries,
+        }
+    }
+
+    #[inline]
+    fn entries_mut(&mut self) -> &mut [TtEntry] {
+        match &mut self.entries {
+            Either::Left(entries) => entries,
+            Either::Right(entries) => entries,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::INFINITY;
+
+    #[test]
+    fn test_transposition_table() {
+        let mut buffer = [TtEntry::EMPTY; 64];
+        let mut table = TranspositionTable::from_buffer(&mut buffer);
+        assert_eq!(table.capacity(), 64);
+        assert_eq!(table.hashfull(), 0);
+
+        let board = Board::default();
+        assert!(table.probe(&board).is_none());
+        table.store(&board, 3, Bound::Lower, 150, Move::Piece(E2, E4));
+        let entry = *table.probe(&board).unwrap();
+        assert_eq!(entry.get_depth(), 3);
+        assert_eq!(entry.get_bound(), Bound::Lower);
+        assert_eq!(entry.get_best_move(), Move::Piece(E2, E4));
+
+        // a shallower search doesn't replace a deeper one
+        table.store(&board, 2, Bound::Exact, 0, Move::Piece(D2, D4));
+        assert_eq!(
+            table.probe(&board).unwrap().get_best_move(),
+            Move::Piece(E2, E4)
+        );
+        assert!(table.probe(&board.change_turn()).is_none());
+
+        let owned = table.into_owned();
+        assert_eq!(owned.capacity(), 64);
+        assert!(owned.probe(&board).is_some());
+        let mut table = TranspositionTable::from_buffer(&mut buffer);
+        table.store(&board, 3, Bound::Lower, 150, Move::Piece(E2, E4));
+
+        table.clear();
+        assert!(table.probe(&board).is_none());
+
+        // an empty table stores nothing
+        let mut table = TranspositionTable::from_buffer(&mut []);
+        table.store(&board, 3, Bound::Exact, 0, Move::Piece(E2, E4));
+        assert!(table.probe(&board).is_none());
+    }
+
+    #[test]
+    fn test_best_move_with_table() {
+        // transpositions start three moves in
+        let board = Board::default();
+        let (_, without_table, _) = board.get_best_next_move_with_table(
+            3,
+            &[],
+            &mut TranspositionTable::from_buffer(&mut []),
+        );
+        let mut table = TranspositionTable::default();
+        let (best_move, with_table, _) = board.get_best_next_move_with_table(3, &[], &mut table);
+        assert!(with_table < without_table);
+        assert!(table.hashfull() > 0);
+
+        // searching again starts from the stored best moves
+        let (again, fewer, _) = board.get_best_next_move_with_table(3, &[], &mut table);
+        assert_eq!(again, best_move);
+        assert!(fewer < with_table);
+
+        // the depth-limited searches have a table of their own
+        let (default_move, with_small_table, _) = board.get_best_next_move(3);
+        assert_eq!(default_move, best_move);
+        assert!(with_small_table < without_table);
+        // king moves transpose into each other everywhere
+        let board = parse_fen("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1").unwrap();
+        let search = |table: &mut TranspositionTable| {
+            let mut board_count = 0;
+            let value = board.minimax_with_table(
+                Either::Left(4),
+                -INFINITY,
+                INFINITY,
+                true,
+                WHITE,
+                &mut board_count,
+                table,
+            );
+            (value, board_count)
+        };
+        let (value, without_table) = search(&mut TranspositionTable::from_buffer(&mut []));
+        let mut board_count = 0;
+        assert_eq!(
+            board.minimax(
+                Either::Left(4),
+                -INFINITY,
+                INFINITY,
+                true,
+                WHITE,
+                &mut board_count
+            ),
+            value
+        );
+        assert!(board_count < without_table);
+    }
+
+    #[test]
+    fn test_search_with_table() {
+        let board = Board::default();
+        let limits = SearchLimits {
+            depth: Some(3),
+            ..SearchLimits::default()
+        };
+        let mut buffer = vec![TtEntry::EMPTY; 4096];
+        let mut table = TranspositionTable::from_buffer(&mut buffer);
+        let result = board.search_with_table(limits, &mut table);
+        assert_eq!(result.best_move, board.search(limits).best_move);
+        assert_eq!(table.capacity(), 4096);
+        assert!(table.probe(&board).is_some());
+
+        let lines = board.analyse_multipv_with_table(limits, 2, &mut table);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].best_move, result.best_move);
+    }
+}
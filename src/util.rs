@@ -7,13 +7,7 @@ use crate::position::Position;
 use crate::{Color, Move};
 
 // generate FEN
-// cannot calculate halfmove or fullmove based on board state,
-// so they must be provided
-pub fn format_fen(
-    board: &Board,
-    halfmove_clock: u8,
-    fullmove_number: u8,
-) -> Result<String, String> {
+pub fn format_fen(board: &Board) -> Result<String, String> {
     let mut fen: Vec<String> = vec![];
 
     // placement
@@ -102,12 +96,12 @@ pub fn format_fen(
 
     // half move
     fen.push(" ".to_string());
-    let halfmove_clock = halfmove_clock.to_string();
+    let halfmove_clock = board.get_halfmove_clock().to_string();
     fen.push(halfmove_clock);
 
     // full move
     fen.push(" ".to_string());
-    let fullmove_number = fullmove_number.to_string();
+    let fullmove_number = board.get_fullmove_number().to_string();
     fen.push(fullmove_number);
 
     Ok(fen.join(""))
@@ -122,16 +116,14 @@ pub fn parse_fen(fen: &str) -> Result<Board, String> {
     let active_color = parts.next();
     let castling = parts.next();
     let en_passant = parts.next();
-    let _halfmove_clock = parts.next();
-    let _fullmove_number = parts.next();
+    let halfmove_clock = parts.next();
+    let fullmove_number = parts.next();
     // make sure all parts present
     if placement.is_none()
         || active_color.is_none()
         || castling.is_none()
         || en_passant.is_none()
-        // ignore half move and full move for now
-        // || halfmove_clock.is_none()
-        // || fullmove_number.is_none()
+        // half move and full move are optional, many tools omit them
         // extra part
         || parts.next().is_some()
     {
@@ -229,111 +221,174 @@ pub fn parse_fen(fen: &str) -> Result<Board, String> {
         },
     });
 
+    if let Some(halfmove_clock) = halfmove_clock {
+        builder = builder.set_halfmove_clock(match halfmove_clock.parse() {
+            Ok(halfmove_clock) => halfmove_clock,
+            Err(_) => {
+                return Err(String::from("invalid halfmove clock"));
+            }
+        });
+    }
+
+    if let Some(fullmove_number) = fullmove_number {
+        builder = builder.set_fullmove_number(match fullmove_number.parse() {
+            Ok(fullmove_number) if fullmove_number > 0 => fullmove_number,
+            _ => {
+                return Err(String::from("invalid fullmove number"));
+            }
+        });
+    }
+
     Ok(builder.build())
 }
 
-// parse Short Algebraic Notation (SAN)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SanError {
+    // more than one legal move matches, e.g. "Nd2" with knights on b1 and f3
+    Ambiguous,
+    // well formed, but no legal move for the current turn matches
+    Illegal,
+    // not standard algebraic notation
+    Malformed,
+}
+
+impl core::fmt::Display for SanError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> Result<(), core::fmt::Error> {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Ambiguous => "ambiguous move",
+                Self::Illegal => "no matching move",
+                Self::Malformed => "malformed move",
+            }
+        )
+    }
+}
+
+// parse Standard Algebraic Notation (SAN)
 //
-// move parsing is strict and should not include any +, #, etc characters.
+// accepts the full SAN grammar, such as "e4", "exd5", "Nbd7", "R1a3",
+// "Qh4xe1", "e8=Q" (or "e8Q"), "O-O" (or "0-0") and "O-O-O" (or "0-0-0").
+// check and mate suffixes ("+", "#") and annotation glyphs ("!", "?",
+// "!?", ...) are ignored, and "-" is accepted between the squares of a
+// long algebraic move like "Ng1-f3".
 //
-// returns Err("ambiguous") if there are multiple possibilities
+// returns Err(SanError::Ambiguous) if there are multiple possibilities
 // e.g. "Nxc4" when there are N at e2 and d6
 // the move should instead specify which N ("Nexc4" or "Ne2xc4")
 //
-// returns Err(InvalidMove) if there is a parse error or the move is
-// not valid based on the current board position and turn.
-pub fn parse_san_move(board: &Board, move_str: &str) -> Result<Move, String> {
-    if move_str == "0-0" {
-        return Ok(Move::KingSideCastle {});
-    } else if move_str == "0-0-0" {
-        return Ok(Move::QueenSideCastle {});
-    }
+// returns Err(SanError::Illegal) if the move is not valid based on the
+// current board position and turn, and Err(SanError::Malformed) if it
+// cannot be parsed at all.
+pub fn parse_san_move(board: &Board, move_str: &str) -> Result<Move, SanError> {
+    let color = board.get_turn_color();
+    let move_str = move_str
+        .trim()
+        .trim_end_matches(|c| matches!(c, '+' | '#' | '!' | '?'));
 
-    // parse in reverse
-    let mut chars = move_str.chars();
+    let castle = match move_str {
+        "O-O" | "0-0" | "o-o" => Some(Move::KingSideCastle),
+        "O-O-O" | "0-0-0" | "o-o-o" => Some(Move::QueenSideCastle),
+        _ => None,
+    };
+    if let Some(castle) = castle {
+        return match board.is_legal_move(castle, color) {
+            true => Ok(castle),
+            false => Err(SanError::Illegal),
+        };
+    }
 
-    // optional pawn promotion
-    let mut last = chars.next_back();
-    let color = board.get_turn_color();
+    let mut chars: Vec<char> = move_str.chars().collect();
     let offboard = Position::new(-1, -1);
-    let move_promotion = match last {
-        Some('Q') => Some(Piece::Queen(color, offboard)),
-        Some('K') => Some(Piece::King(color, offboard)),
-        Some('N') => Some(Piece::Knight(color, offboard)),
-        Some('B') => Some(Piece::Bishop(color, offboard)),
-        Some('R') => Some(Piece::Rook(color, offboard)),
+    let piece_for = |c: char| match c {
+        'K' => Some(Piece::King(color, offboard)),
+        'Q' => Some(Piece::Queen(color, offboard)),
+        'R' => Some(Piece::Rook(color, offboard)),
+        'B' => Some(Piece::Bishop(color, offboard)),
+        'N' => Some(Piece::Knight(color, offboard)),
+        _ => None,
+    };
+    let column_for = |c: char| match c {
+        'a'..='h' => Some(c as i32 - 'a' as i32),
         _ => None,
     };
-    if move_promotion.is_some() {
-        // there is a move promotion, move to next char for parsing
-        last = chars.next_back();
+    let row_for = |c: char| match c {
+        '1'..='8' => Some(c as i32 - '1' as i32),
+        _ => None,
+    };
+
+    // optional pawn promotion, "=Q" or "Q"
+    let mut move_promotion = None;
+    if let Some(promotion) = chars.last().and_then(|c| piece_for(*c)) {
+        chars.pop();
+        if chars.last() == Some(&'=') {
+            chars.pop();
+        }
+        move_promotion = Some(promotion);
     }
 
     // to position
-    let to: String = vec![chars.next_back().unwrap_or(' '), last.unwrap_or(' ')]
-        .into_iter()
-        .collect();
-    let move_to = match Position::pgn(&to) {
-        Ok(position) => position,
-        Err(_) => {
-            return Err("invalid to position".to_string());
+    if chars.len() < 2 {
+        return Err(SanError::Malformed);
+    }
+    let move_to = match (
+        column_for(chars[chars.len() - 2]),
+        row_for(chars[chars.len() - 1]),
+    ) {
+        (Some(col), Some(row)) => Position::new(row, col),
+        _ => return Err(SanError::Malformed),
+    };
+    chars.truncate(chars.len() - 2);
+
+    // optional piece type, pawn if missing
+    let mut source = &chars[..];
+    let piece = match source.first().and_then(|c| piece_for(*c)) {
+        Some(piece) => {
+            source = &source[1..];
+            piece
         }
+        None => Piece::Pawn(color, offboard),
     };
 
-    // chars is now source, with possible 'x' take at end
-    let mut source_column = chars.next();
-
-    let piece = match source_column {
-        Some('B') => Piece::Bishop(color, offboard),
-        Some('K') => Piece::King(color, offboard),
-        Some('N') => Piece::Knight(color, offboard),
-        Some('Q') => Piece::Queen(color, offboard),
-        Some('R') => Piece::Rook(color, offboard),
-        _ => Piece::Pawn(color, offboard),
-    };
-    if piece != Piece::Pawn(color, offboard) {
-        // source_column was piece type, next char is source_column
-        source_column = chars.next();
+    // optional capture or long algebraic separator
+    if let Some('x' | ':' | '-') = source.last() {
+        source = &source[..source.len() - 1];
     }
 
-    // filter by source column if specified
-    let column = match source_column {
-        Some('a') => Some(0),
-        Some('b') => Some(1),
-        Some('c') => Some(2),
-        Some('d') => Some(3),
-        Some('e') => Some(4),
-        Some('f') => Some(5),
-        Some('g') => Some(6),
-        Some('h') => Some(7),
-        // no column specified
-        _ => None,
-    };
+    // optional source column and row to disambiguate
+    let mut column = None;
+    let mut row = None;
+    if let Some(c) = source.first().and_then(|c| column_for(*c)) {
+        column = Some(c);
+        source = &source[1..];
+    }
+    if let Some(r) = source.first().and_then(|c| row_for(*c)) {
+        row = Some(r);
+        source = &source[1..];
+    }
+    if !source.is_empty() {
+        return Err(SanError::Malformed);
+    }
 
-    // if column not specified, may be row
-    let mut source_row = source_column;
-    if column.is_some() {
-        // if column specified, row may be next char
-        source_row = chars.next();
+    // pawns can only change column by capturing, which names the column
+    if piece.is_pawn() && column.is_none() {
+        column = Some(move_to.get_col());
     }
 
-    // filter by source row if specified
-    let row = match source_row {
-        Some('1') => Some(0),
-        Some('2') => Some(1),
-        Some('3') => Some(2),
-        Some('4') => Some(3),
-        Some('5') => Some(4),
-        Some('6') => Some(5),
-        Some('7') => Some(6),
-        Some('8') => Some(7),
-        _ => None,
-    };
+    if let Some(promotion) = move_promotion {
+        if !piece.is_pawn()
+            || promotion.is_king()
+            || !(move_to.get_row() == 0 || move_to.get_row() == 7)
+        {
+            return Err(SanError::Illegal);
+        }
+    }
 
     // find moves that end on target square and are correct piece type
-    let mut candidates = vec![];
+    let mut candidates: Vec<Piece> = vec![];
     for legal_move in board.get_legal_moves() {
-        if let Move::Piece(from, to) = legal_move {
+        if let Move::Piece(from, to) | Move::Promotion(from, to, _) = legal_move {
             if move_to == to {
                 if let Some(board_piece) = board.get_piece(from) {
                     // filter based on type
@@ -341,6 +396,7 @@ pub fn parse_san_move(board: &Board, move_str: &str) -> Result<Move, String> {
                     if board_piece.get_name() == piece.get_name()
                         && (column.is_none() || column == Some(pos.get_col()))
                         && (row.is_none() || row == Some(pos.get_row()))
+                        && !candidates.contains(&board_piece)
                     {
                         candidates.push(board_piece);
                     }
@@ -350,15 +406,15 @@ pub fn parse_san_move(board: &Board, move_str: &str) -> Result<Move, String> {
     }
 
     match candidates.len() {
-        0 => Err("no matching move".to_string()),
+        0 => Err(SanError::Illegal),
         1 => {
             let move_from = candidates[0].get_pos();
             match move_promotion {
                 None => Ok(Move::Piece(move_from, move_to)),
-                Some(piece) => Ok(Move::Promotion(move_from, move_to, piece)),
+                Some(piece) => Ok(Move::Promotion(move_from, move_to, piece.move_to(move_to))),
             }
         }
-        _ => Err("ambiguous move".to_string()),
+        _ => Err(SanError::Ambiguous),
     }
 }
 
@@ -376,17 +432,17 @@ mod tests {
         let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
 
         let board = Board::default();
-        assert_eq!(format_fen(&board, 0, 1).unwrap(), String::from(start));
+        assert_eq!(format_fen(&board).unwrap(), String::from(start));
 
         let board = parse_fen(start).unwrap();
-        assert_eq!(format_fen(&board, 0, 1).unwrap(), String::from(start));
+        assert_eq!(format_fen(&board).unwrap(), String::from(start));
 
         let board = match board.play_move(Move::Piece(E2, E4)) {
             GameResult::Continuing(board) => board,
             _ => panic!("e4 failed"),
         };
         assert_eq!(
-            format_fen(&board, 0, 1).unwrap(),
+            format_fen(&board).unwrap(),
             "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1".to_string()
         );
 
@@ -395,7 +451,7 @@ mod tests {
             _ => panic!("c5 failed"),
         };
         assert_eq!(
-            format_fen(&board, 0, 2).unwrap(),
+            format_fen(&board).unwrap(),
             "rnbqkbnr/pp1ppppp/8/2p5/4P3/8/PPPP1PPP/RNBQKBNR w KQkq c6 0 2".to_string()
         );
 
@@ -404,9 +460,53 @@ mod tests {
             _ => panic!("Nf3 failed"),
         };
         assert_eq!(
-            format_fen(&board, 1, 2).unwrap(),
+            format_fen(&board).unwrap(),
             "rnbqkbnr/pp1ppppp/8/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2".to_string()
-        )
+        );
+
+        // move counters round trip
+        let midgame = "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4";
+        let board = parse_fen(midgame).unwrap();
+        assert_eq!(board.get_halfmove_clock(), 4);
+        assert_eq!(board.get_fullmove_number(), 4);
+        assert_eq!(format_fen(&board).unwrap(), midgame.to_string());
+
+        // counters are optional
+        let board = parse_fen("8/8/8/8/8/8/8/K1k5 w - -").unwrap();
+        assert_eq!(board.get_halfmove_clock(), 0);
+        assert_eq!(board.get_fullmove_number(), 1);
+
+        assert!(parse_fen("8/8/8/8/8/8/8/K1k5 w - - x 1").is_err());
+        assert!(parse_fen("8/8/8/8/8/8/8/K1k5 w - - 0 0").is_err());
+    }
+
+    #[test]
+    fn test_move_rules() {
+        let board = parse_fen("8/8/8/4k3/8/8/4K3/7R w - - 99 80").unwrap();
+        assert!(!board.can_claim_fifty_move_draw());
+        let board = match board.play_move(Move::Piece(H1, H2)) {
+            GameResult::Continuing(board) => board,
+            e => panic!("unexpected result: {:?}", e),
+        };
+        assert!(board.can_claim_fifty_move_draw());
+        assert_eq!(board.get_fullmove_number(), 80);
+
+        let board = match board.play_move(Move::Piece(E5, E4)) {
+            GameResult::Continuing(board) => board,
+            e => panic!("unexpected result: {:?}", e),
+        };
+        assert_eq!(board.get_halfmove_clock(), 101);
+        assert_eq!(board.get_fullmove_number(), 81);
+
+        let board = parse_fen("8/8/8/4k3/8/8/4K3/7R w - - 149 120").unwrap();
+        assert_eq!(board.play_move(Move::Piece(H1, H2)), GameResult::Stalemate);
+
+        // a pawn move resets the clock
+        let board = parse_fen("8/8/8/4k3/8/8/P3K3/8 w - - 149 120").unwrap();
+        match board.play_move(Move::Piece(A2, A3)) {
+            GameResult::Continuing(board) => assert_eq!(board.get_halfmove_clock(), 0),
+            e => panic!("unexpected result: {:?}", e),
+        }
     }
 
     #[test]
@@ -425,7 +525,7 @@ mod tests {
         // not valid first move for white
         assert_eq!(
             parse_san_move(&board, "d5").expect_err("d5"),
-            "no matching move".to_string()
+            SanError::Illegal
         );
 
         // make first move
@@ -441,7 +541,102 @@ mod tests {
         // white moves not valid for black
         assert_eq!(
             parse_san_move(&board, "c4").expect_err("c4"),
-            "no matching move".to_string()
+            SanError::Illegal
+        );
+    }
+
+    #[test]
+    fn test_parse_san_move_suffixes() {
+        let board = Board::default();
+        for san in [
+            "Nf3", "Nf3+", "Nf3#", "Nf3!", "Nf3?!", "Nf3+!?", "Ng1f3", "Ng1-f3",
+        ] {
+            assert_eq!(parse_san_move(&board, san).expect(san), Move::Piece(G1, F3));
+        }
+        assert_eq!(
+            parse_san_move(&board, "e2-e4").expect("e2-e4"),
+            Move::Piece(E2, E4)
+        );
+
+        for san in ["", "e", "e9", "Zf3", "Nf3x", "Nxxf3", "f3N3"] {
+            assert_eq!(
+                parse_san_move(&board, san).expect_err(san),
+                SanError::Malformed
+            );
+        }
+        // castling is well formed, but blocked
+        assert_eq!(
+            parse_san_move(&board, "O-O").expect_err("O-O"),
+            SanError::Illegal
+        );
+        // pawns cannot capture without an enemy piece
+        assert_eq!(
+            parse_san_move(&board, "exd3").expect_err("exd3"),
+            SanError::Illegal
+        );
+    }
+
+    #[test]
+    fn test_parse_san_move_special() {
+        // promotion, in all spellings
+        let board = parse_fen("3r3k/4P3/8/8/8/8/8/K7 w - - 0 1").unwrap();
+        for san in ["e8=Q+", "e8Q+", "e8=Q"] {
+            assert_eq!(
+                parse_san_move(&board, san).expect(san),
+                Move::Promotion(E7, E8, Piece::Queen(Color::White, E8))
+            );
+        }
+        assert_eq!(
+            parse_san_move(&board, "exd8=N").expect("exd8=N"),
+            Move::Promotion(E7, D8, Piece::Knight(Color::White, D8))
+        );
+        assert!(board
+            .get_legal_moves()
+            .any(|m| m == Move::Promotion(E7, D8, Piece::Knight(Color::White, D8))));
+        assert_eq!(
+            parse_san_move(&board, "e8=K").expect_err("e8=K"),
+            SanError::Illegal
+        );
+        assert_eq!(
+            parse_san_move(&board, "Ka2=Q").expect_err("Ka2=Q"),
+            SanError::Illegal
+        );
+
+        // castling, in all spellings
+        let board = parse_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        for san in ["O-O", "0-0", "O-O+"] {
+            assert_eq!(
+                parse_san_move(&board, san).expect(san),
+                Move::KingSideCastle
+            );
+        }
+        for san in ["O-O-O", "0-0-0"] {
+            assert_eq!(
+                parse_san_move(&board, san).expect(san),
+                Move::QueenSideCastle
+            );
+        }
+
+        // ambiguous moves need disambiguation
+        let board = parse_fen("4k3/8/8/R7/8/8/8/R3K3 w - - 0 1").unwrap();
+        assert_eq!(
+            parse_san_move(&board, "Ra3").expect_err("Ra3"),
+            SanError::Ambiguous
+        );
+        assert_eq!(
+            parse_san_move(&board, "R1a3").expect("R1a3"),
+            Move::Piece(A1, A3)
+        );
+        assert_eq!(
+            parse_san_move(&board, "Ra5a3").expect("Ra5a3"),
+            Move::Piece(A5, A3)
+        );
+
+        // en passant
+        let board = parse_fen("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 2").unwrap();
+        assert_eq!(
+            parse_san_move(&board, "exd6").expect("exd6"),
+            Move::Piece(E5, D6)
         );
     }
 }
@@ -0,0 +1,83 @@
+// keys for Zobrist hashing of positions.
+//
+// a position's hash is the XOR of a key for each piece on its square, the
+// side to move, the castling rights and the en passant file. moves update
+// it by XORing out what changed and XORing in the result.
+//
+// the keys come from a SplitMix64 generator with a fixed seed, evaluated at
+// compile time, so hashes are identical across builds and platforms and
+// can be persisted.
+use super::*;
+use crate::board::CastlingRights;
+
+const SEED: u64 = 0x2545_f491_4f6c_dd1d;
+
+// keys are taken from the generator in this order
+const PIECE_KEYS: usize = 12 * 64;
+const SIDE_KEY: usize = PIECE_KEYS;
+const CASTLING_KEYS: usize = SIDE_KEY + 1;
+const EN_PASSANT_KEYS: usize = CASTLING_KEYS + 4;
+const KEY_COUNT: usize = EN_PASSANT_KEYS + 8;
+
+const KEYS: [u64; KEY_COUNT] = {
+    let mut keys = [0; KEY_COUNT];
+    let mut state = SEED;
+    let mut i = 0;
+    while i < KEY_COUNT {
+        // SplitMix64
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        keys[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    keys
+};
+
+// key for a piece standing on its position.
+#[inline]
+pub(crate) fn piece_key(piece: Piece) -> u64 {
+    let kind = piece.get_kind_index();
+    let color = match piece.get_color() {
+        WHITE => 0,
+        BLACK => 6,
+    };
+    let pos = piece.get_pos();
+    KEYS[(color + kind) * 64 + (pos.get_row() * 8 + pos.get_col()) as usize]
+}
+
+// key toggled when it is black's turn.
+#[inline]
+pub(crate) fn side_key() -> u64 {
+    KEYS[SIDE_KEY]
+}
+
+// key for the castling rights of both players.
+#[inline]
+pub(crate) fn castling_key(white: CastlingRights, black: CastlingRights) -> u64 {
+    let mut key = 0;
+    for (i, allowed) in [
+        white.can_kingside_castle(),
+        white.can_queenside_castle(),
+        black.can_kingside_castle(),
+        black.can_queenside_castle(),
+    ]
+    .iter()
+    .enumerate()
+    {
+        if *allowed {
+            key ^= KEYS[CASTLING_KEYS + i];
+        }
+    }
+    key
+}
+
+// key for the file of the en passant square, if there is one.
+#[inline]
+pub(crate) fn en_passant_key(en_passant: Option<Position>) -> u64 {
+    match en_passant {
+        Some(pos) => KEYS[EN_PASSANT_KEYS + pos.get_col() as usize],
+        None => 0,
+    }
+}